@@ -0,0 +1,354 @@
+pub mod cache;
+mod deepl;
+mod dry_run;
+pub mod journal;
+mod pseudo;
+
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use color_print::ceprintln;
+use serde::{Deserialize, Serialize};
+
+use crate::helper_functions::hash_text;
+use crate::types::Language;
+
+pub use cache::CachedBackend;
+pub use deepl::DeepLBackend;
+pub use dry_run::DryRunBackend;
+pub use journal::JournaledBackend;
+pub use pseudo::PseudoBackend;
+
+/// The environment variable which can be used to select a backend instead of `--backend`.
+pub const BACKEND_ENV_VAR: &str = "LTRANSLATE_BACKEND";
+/// The number of requests which may be in progress at once, unless configured otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// The translation services available to ltranslate.
+#[derive(Clone, Copy)]
+pub enum BackendKind {
+    DeepL,
+    Pseudo,
+}
+
+/// A service capable of translating batches of text, such as DeepL.
+///
+/// Project mode only interacts with translation services through this trait, so new engines,
+/// offline test doubles, and caching layers can be added without touching the project logic.
+///
+/// Backends are shared between threads so that several languages can be translated at once.
+pub trait TranslationBackend: Sync {
+    /// Get a user-facing name for the backend, used in status and error messages.
+    fn name(&self) -> &str;
+
+    /// Get the identifier of the translation service the backend uses, as in
+    /// [`BackendKind::identifier()`]. Glossaries only exist on the service they were created with.
+    fn identifier(&self) -> &str;
+
+    /// Get all languages the backend is able to translate from.
+    fn source_languages(&self) -> &[Language];
+
+    /// Get all languages the backend is able to translate into.
+    fn target_languages(&self) -> &[Language];
+
+    /// Translate a batch of texts, returning the translations in the same order as the request.
+    fn translate(&self, request: TranslationRequest) -> Result<Vec<String>, TranslationError>;
+
+    /// Get the amount of translation credit used and available for the current billing period.
+    fn usage(&self) -> Result<Usage, TranslationError>;
+
+    /// Check whether [`TranslationRequest::formality`] has any effect on translations into a given
+    /// target language.
+    fn supports_formality(&self, _language_code: &str) -> bool {
+        false
+    }
+
+    /// Create a glossary of term pairs which can be applied to later requests, returning its ID.
+    fn create_glossary(
+        &self,
+        _name: &str,
+        _source_language: &str,
+        _target_language: &str,
+        _entries: &[(String, String)],
+    ) -> Result<String, TranslationError> {
+        Err(TranslationError::Request(format!(
+            "the {} backend does not support glossaries",
+            self.name()
+        )))
+    }
+
+    /// Delete a glossary created by [`Self::create_glossary()`]. Glossaries which no longer exist
+    /// are ignored.
+    fn delete_glossary(&self, _glossary_id: &str) -> Result<(), TranslationError> {
+        Err(TranslationError::Request(format!(
+            "the {} backend does not support glossaries",
+            self.name()
+        )))
+    }
+}
+
+impl BackendKind {
+    /// All backend identifiers accepted by `--backend` and [`BACKEND_ENV_VAR`].
+    pub const IDENTIFIERS: [&'static str; 2] = ["deepl", "pseudo"];
+
+    /// Parse a backend identifier, ignoring case.
+    pub fn from_identifier(identifier: &str) -> Option<Self> {
+        match identifier.to_lowercase().as_str() {
+            "deepl" => Some(BackendKind::DeepL),
+            "pseudo" => Some(BackendKind::Pseudo),
+            _ => None,
+        }
+    }
+
+    /// Get the identifier used to select this backend.
+    pub fn identifier(self) -> &'static str {
+        match self {
+            BackendKind::DeepL => "deepl",
+            BackendKind::Pseudo => "pseudo",
+        }
+    }
+
+    /// Connect to the backend, exiting if it is unavailable.
+    ///
+    /// If `dry_run` is set, a [`DryRunBackend`] is returned instead, so that no requests are made.
+    pub fn connect(self, dry_run: bool, settings: RequestSettings) -> Box<dyn TranslationBackend> {
+        if dry_run {
+            return Box::new(DryRunBackend::new(self.identifier()));
+        }
+
+        match self {
+            BackendKind::DeepL => Box::new(DeepLBackend::connect(settings)),
+            BackendKind::Pseudo => Box::new(PseudoBackend::new()),
+        }
+    }
+}
+
+/// A batch of texts to be translated from one language to another.
+pub struct TranslationRequest<'a> {
+    pub source_language: &'a str,
+    pub target_language: &'a str,
+    pub texts: &'a [String],
+    /// The glossary to apply, as returned by [`TranslationBackend::create_glossary()`].
+    pub glossary_id: Option<&'a str>,
+    /// How formal the translations should be, if the target language supports it.
+    pub formality: Formality,
+    /// A description of where the texts are used, which helps to translate them but is not
+    /// translated itself.
+    pub context: Option<&'a str>,
+    /// Called with each group of texts and their translations as soon as they are translated, by
+    /// backends which split requests into several batches.
+    pub on_batch: Option<&'a BatchCallback<'a>>,
+}
+
+impl TranslationRequest<'_> {
+    /// Get a key which identifies the options that affect how the texts are translated, so that
+    /// translations made with different options are never mixed up when they are reused.
+    pub fn options_key(&self) -> String {
+        let mut key = String::new();
+        if self.formality != Formality::Default {
+            key.push_str(&format!("+formality:{}", self.formality));
+        }
+
+        if let Some(glossary_id) = self.glossary_id {
+            key.push_str(&format!("+glossary:{glossary_id}"));
+        }
+
+        // The context itself can be long, so only a hash of it is used.
+        if let Some(context) = self.context {
+            key.push_str(&format!("+context:{}", &hash_text(context)[..16]));
+        }
+
+        key
+    }
+}
+
+/// See [`TranslationRequest::on_batch`].
+pub type BatchCallback<'a> = dyn Fn(&[String], &[String]) + Sync + 'a;
+
+/// Character usage information reported by a [`TranslationBackend`].
+pub struct Usage {
+    pub character_count: u64,
+    pub character_limit: u64,
+}
+
+/// Settings which control how a backend sends requests.
+#[derive(Clone, Copy)]
+pub struct RequestSettings {
+    pub retry_policy: RetryPolicy,
+    /// The largest number of requests which may be in progress at once.
+    pub concurrency: usize,
+}
+
+/// How often, and for how long, to retry requests which fail because of a temporary problem.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// The number of times a failed request is retried before giving up.
+    pub max_retries: u32,
+    /// The longest time to wait between two attempts, in seconds.
+    pub max_wait_seconds: u64,
+}
+
+/// How formal translations should be, for target languages which distinguish between formal and
+/// informal language (such as "Sie" and "du" in German).
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Formality {
+    #[default]
+    Default,
+    More,
+    Less,
+    /// Like [`Formality::More`], but languages which do not support formality are not warned about.
+    PreferMore,
+    /// Like [`Formality::Less`], but languages which do not support formality are not warned about.
+    PreferLess,
+}
+
+pub enum TranslationError {
+    /// The backend refused the credentials it was given.
+    Authorization,
+    /// The character quota for the current billing period has been used up.
+    QuotaExceeded,
+    /// The backend is receiving too many requests.
+    RateLimited,
+    /// The backend is temporarily unable to handle requests.
+    Unavailable(String),
+    /// The backend could not be reached over the network.
+    Unreachable(String),
+    /// The backend responded with an error.
+    Request(String),
+    /// The user asked for translation to stop before the request was finished.
+    Cancelled,
+    /// The backend returned a different number of translations than it was sent.
+    MismatchedResponse { expected: usize, received: usize },
+}
+
+impl Display for TranslationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TranslationError::Authorization => write!(
+                f,
+                "the translation service rejected the provided API key; check that it is correct and has not been revoked"
+            ),
+            TranslationError::QuotaExceeded => write!(
+                f,
+                "the character quota for the current billing period has been used up; wait for it to reset or upgrade your plan"
+            ),
+            TranslationError::RateLimited => write!(
+                f,
+                "the translation service is receiving too many requests; wait a while and try again"
+            ),
+            TranslationError::Unavailable(message) => write!(
+                f,
+                "the translation service is temporarily unavailable ({message}); try again later"
+            ),
+            TranslationError::Unreachable(message) => write!(
+                f,
+                "the translation service could not be reached ({message}); check your internet connection"
+            ),
+            TranslationError::Request(message) => write!(f, "{message}"),
+            TranslationError::Cancelled => write!(f, "translation was interrupted"),
+            TranslationError::MismatchedResponse { expected, received } => write!(
+                f,
+                "expected {expected} translated values but received {received}"
+            ),
+        }
+    }
+}
+
+impl Formality {
+    /// All formality identifiers accepted by `--formality` and the manifest.
+    pub const IDENTIFIERS: [&'static str; 5] =
+        ["default", "more", "less", "prefer_more", "prefer_less"];
+
+    /// Parse a formality identifier, ignoring case.
+    pub fn from_identifier(identifier: &str) -> Option<Self> {
+        match identifier.to_lowercase().as_str() {
+            "default" => Some(Formality::Default),
+            "more" => Some(Formality::More),
+            "less" => Some(Formality::Less),
+            "prefer_more" => Some(Formality::PreferMore),
+            "prefer_less" => Some(Formality::PreferLess),
+            _ => None,
+        }
+    }
+
+    /// Get the identifier used to select this formality.
+    pub fn identifier(self) -> &'static str {
+        match self {
+            Formality::Default => "default",
+            Formality::More => "more",
+            Formality::Less => "less",
+            Formality::PreferMore => "prefer_more",
+            Formality::PreferLess => "prefer_less",
+        }
+    }
+
+    /// Check whether languages which do not support formality should be skipped silently, instead
+    /// of being warned about.
+    pub fn is_preference(self) -> bool {
+        matches!(self, Formality::PreferMore | Formality::PreferLess)
+    }
+}
+
+impl Display for Formality {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.identifier())
+    }
+}
+
+impl TranslationError {
+    /// Check whether the error is caused by a temporary problem, meaning the request may succeed if
+    /// it is retried.
+    ///
+    /// A used-up quota counts as temporary, since it can be raised while the command is waiting,
+    /// such as by upgrading the plan or raising a cost limit.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            TranslationError::RateLimited
+                | TranslationError::QuotaExceeded
+                | TranslationError::Unavailable(_)
+                | TranslationError::Unreachable(_)
+        )
+    }
+}
+
+impl RetryPolicy {
+    /// Run a request, retrying it with exponential backoff for as long as it fails with a transient
+    /// error and retries remain.
+    pub fn run<T>(
+        &self,
+        mut request: impl FnMut() -> Result<T, TranslationError>,
+    ) -> Result<T, TranslationError> {
+        let mut attempt = 0;
+        loop {
+            match request() {
+                Err(e) if e.is_transient() && attempt < self.max_retries => {
+                    let wait = Duration::from_secs(
+                        2u64.saturating_pow(attempt).min(self.max_wait_seconds),
+                    );
+                    attempt += 1;
+                    ceprintln!(
+                        "<y>Request failed:</> {}. Retrying in {} seconds (attempt {} of {})...",
+                        e,
+                        wait.as_secs(),
+                        attempt,
+                        self.max_retries
+                    );
+                    std::thread::sleep(wait);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 5,
+            max_wait_seconds: 60,
+        }
+    }
+}
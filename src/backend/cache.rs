@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use color_print::ceprintln;
+
+use crate::backend::{
+    BackendKind, TranslationBackend, TranslationError, TranslationRequest, Usage,
+};
+use crate::helper_functions::create_directory_if_not_exists;
+use crate::types::{Language, LocaleDocument, LocaleManifest};
+use crate::{APP_DIR_PATH, TRANSLATION_MEMORY_PATH, exit};
+
+/// Previously translated texts, grouped by the options they were translated with (such as the
+/// backend which produced them), then by language pair (such as `EN:DE`), then by source text.
+type TranslationMemory = BTreeMap<String, BTreeMap<String, BTreeMap<String, String>>>;
+
+/// How long new translations may be kept before the translation memory file is written. The whole
+/// file is rewritten each time, so writing it after every request would hold up the other requests
+/// in progress.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A [`TranslationBackend`] which stores every translation it receives in a persistent translation
+/// memory, so that texts which have been translated before are never sent to the inner backend
+/// again.
+///
+/// Source texts are stored exactly as the inner backend sees them, meaning after placeholders have
+/// been shielded and ICU messages have been split up.
+pub struct CachedBackend {
+    inner: Box<dyn TranslationBackend>,
+    memory: Mutex<TranslationMemory>,
+    options: String,
+    persist: bool,
+    /// When the oldest translation which has not been written to the memory file yet was
+    /// received, if there is one.
+    unsaved_since: Mutex<Option<Instant>>,
+    /// Set once a request has failed, after which new translations are written straight away, as
+    /// the command may exit before they would be written otherwise.
+    failed: AtomicBool,
+}
+
+/// A [`TranslationBackend`] which records the texts it is asked to translate, along with the
+/// options and language pair they would be translated with by the backend with a given identifier,
+/// used to find out which translation memory entries are still needed.
+struct UsageRecorder {
+    identifier: &'static str,
+    used: Mutex<BTreeSet<(String, String, String)>>,
+}
+
+impl CachedBackend {
+    /// Wrap a backend using the translation memory in the project directory.
+    ///
+    /// Entries are only shared between backends with the same `options`. If `persist` is not set,
+    /// the memory is only read from, which is used for dry runs, as their "translations" must not
+    /// be stored.
+    pub fn new(inner: Box<dyn TranslationBackend>, options: &str, persist: bool) -> Self {
+        CachedBackend {
+            inner,
+            memory: Mutex::new(read_memory()),
+            options: options.to_owned(),
+            persist,
+            unsaved_since: Mutex::new(None),
+            failed: AtomicBool::new(false),
+        }
+    }
+
+    /// Add new translations to the memory, writing it out if it has not been written for
+    /// [`SAVE_INTERVAL`] or a request has failed.
+    fn store(&self, options: String, pair: String, translations: BTreeMap<String, String>) {
+        let mut memory = self.memory.lock().unwrap();
+        memory
+            .entry(options)
+            .or_default()
+            .entry(pair)
+            .or_default()
+            .extend(translations);
+
+        let mut unsaved_since = self.unsaved_since.lock().unwrap();
+        let oldest_unsaved = *unsaved_since.get_or_insert_with(Instant::now);
+        if self.failed.load(Ordering::Relaxed) || oldest_unsaved.elapsed() >= SAVE_INTERVAL {
+            write_memory(&memory);
+            *unsaved_since = None;
+        }
+    }
+
+    /// Write out any translations which have not been written to the memory file yet.
+    fn flush(&self) {
+        let memory = self.memory.lock().unwrap();
+        if self.unsaved_since.lock().unwrap().take().is_some() {
+            write_memory(&memory);
+        }
+    }
+
+    /// Note that a request has failed, writing out the translations received so far.
+    fn fail(&self) {
+        self.failed.store(true, Ordering::Relaxed);
+        self.flush();
+    }
+}
+
+impl Drop for CachedBackend {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl TranslationBackend for CachedBackend {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn identifier(&self) -> &str {
+        self.inner.identifier()
+    }
+
+    fn source_languages(&self) -> &[Language] {
+        self.inner.source_languages()
+    }
+
+    fn target_languages(&self) -> &[Language] {
+        self.inner.target_languages()
+    }
+
+    fn translate(&self, request: TranslationRequest) -> Result<Vec<String>, TranslationError> {
+        let pair = language_pair(request.source_language, request.target_language);
+        let options = format!("{}{}", self.options, request.options_key());
+        let mut translations = {
+            let memory = self.memory.lock().unwrap();
+            let cached = memory.get(&options).and_then(|p| p.get(&pair));
+            request
+                .texts
+                .iter()
+                .map(|t| cached.and_then(|c| c.get(t)).cloned())
+                .collect::<Vec<_>>()
+        };
+
+        // Identical texts within a request are only sent once.
+        let mut misses = Vec::new();
+        for (text, cached) in request.texts.iter().zip(&translations) {
+            if cached.is_none() && !misses.contains(text) {
+                misses.push(text.clone());
+            }
+        }
+
+        let hit_count = translations.iter().filter(|t| t.is_some()).count();
+        if hit_count > 0 {
+            ceprintln!(
+                "Reused <c>{}</> translations from the translation memory for locale <g>'{}'</>.",
+                hit_count,
+                request.target_language
+            );
+        }
+
+        if misses.is_empty() {
+            return Ok(translations.into_iter().flatten().collect());
+        }
+
+        let translated_misses = self.inner.translate(TranslationRequest {
+            source_language: request.source_language,
+            target_language: request.target_language,
+            texts: &misses,
+            glossary_id: request.glossary_id,
+            formality: request.formality,
+            context: request.context,
+            on_batch: request.on_batch,
+        });
+        let translated_misses = translated_misses.inspect_err(|_| self.fail())?;
+
+        if translated_misses.len() != misses.len() {
+            self.fail();
+            return Err(TranslationError::MismatchedResponse {
+                expected: misses.len(),
+                received: translated_misses.len(),
+            });
+        }
+
+        let translated_misses = misses
+            .into_iter()
+            .zip(translated_misses)
+            .collect::<BTreeMap<_, _>>();
+        for (text, translation) in request.texts.iter().zip(translations.iter_mut()) {
+            if translation.is_none() {
+                *translation = translated_misses.get(text).cloned();
+            }
+        }
+
+        if self.persist {
+            self.store(options, pair, translated_misses);
+        }
+
+        Ok(translations.into_iter().flatten().collect())
+    }
+
+    fn usage(&self) -> Result<Usage, TranslationError> {
+        self.inner.usage()
+    }
+
+    fn supports_formality(&self, language_code: &str) -> bool {
+        self.inner.supports_formality(language_code)
+    }
+}
+
+impl TranslationBackend for UsageRecorder {
+    fn name(&self) -> &str {
+        "translation memory"
+    }
+
+    fn identifier(&self) -> &str {
+        self.identifier
+    }
+
+    fn source_languages(&self) -> &[Language] {
+        &[]
+    }
+
+    fn target_languages(&self) -> &[Language] {
+        &[]
+    }
+
+    fn translate(&self, request: TranslationRequest) -> Result<Vec<String>, TranslationError> {
+        let pair = language_pair(request.source_language, request.target_language);
+        let options = format!("{}{}", self.identifier, request.options_key());
+        let mut used = self.used.lock().unwrap();
+        for text in request.texts {
+            used.insert((options.clone(), pair.clone(), text.clone()));
+        }
+
+        Ok(request.texts.to_vec())
+    }
+
+    fn usage(&self) -> Result<Usage, TranslationError> {
+        Ok(Usage {
+            character_count: 0,
+            character_limit: u64::MAX,
+        })
+    }
+
+    /// Every language is accepted, so that no formality warnings are printed while pruning.
+    fn supports_formality(&self, _language_code: &str) -> bool {
+        true
+    }
+}
+
+/// Remove every entry from the translation memory which would not be used to translate the current
+/// source locale into the project's enabled languages, returning the number of entries removed.
+pub fn prune(manifest_data: &LocaleManifest) -> usize {
+    let Some(source_document) = LocaleDocument::source(manifest_data) else {
+        exit!("Missing source locale data.");
+    };
+
+    // Each backend uses its own glossaries, which are part of the options entries are grouped by,
+    // so the texts are recorded once for each backend.
+    let source_text = LocaleDocument::get_raw_text_data(&source_document.flat_data());
+    let mut used = BTreeSet::new();
+    for identifier in BackendKind::IDENTIFIERS {
+        let recorder = UsageRecorder {
+            identifier,
+            used: Mutex::new(BTreeSet::new()),
+        };
+        for lang in &manifest_data.languages {
+            if LocaleDocument::translate_full(
+                &recorder,
+                manifest_data,
+                &source_document,
+                &source_text,
+                lang.clone(),
+            )
+            .is_err()
+            {
+                exit!(
+                    "Failed to determine which translations locale <g>'{}'</> uses.",
+                    lang.code
+                );
+            }
+        }
+
+        used.extend(recorder.used.into_inner().unwrap());
+    }
+
+    let mut memory = read_memory();
+    let mut removed_count = 0;
+    for (options, pairs) in memory.iter_mut() {
+        for (pair, entries) in pairs.iter_mut() {
+            let entry_count = entries.len();
+            entries.retain(|source, _| {
+                used.contains(&(options.clone(), pair.clone(), source.clone()))
+            });
+            removed_count += entry_count - entries.len();
+        }
+
+        pairs.retain(|_, entries| !entries.is_empty());
+    }
+
+    memory.retain(|_, pairs| !pairs.is_empty());
+    write_memory(&memory);
+
+    removed_count
+}
+
+fn language_pair(source_language: &str, target_language: &str) -> String {
+    format!("{source_language}:{target_language}")
+}
+
+/// Read the translation memory file, or start an empty memory if it does not exist yet.
+fn read_memory() -> TranslationMemory {
+    let Ok(memory) = std::fs::read_to_string(TRANSLATION_MEMORY_PATH) else {
+        return TranslationMemory::new();
+    };
+
+    let Ok(memory) = serde_json::from_str(&memory) else {
+        exit!(
+            "Failed to parse translation memory file. Remove <g>'{}'</> or run with <m>--no-cache</>.",
+            TRANSLATION_MEMORY_PATH
+        );
+    };
+
+    memory
+}
+
+fn write_memory(memory: &TranslationMemory) {
+    let Ok(formatted_data) = serde_json::to_string_pretty(memory) else {
+        exit!("Unknown error occured when serializing translation memory.");
+    };
+
+    create_directory_if_not_exists(APP_DIR_PATH);
+    if std::fs::write(TRANSLATION_MEMORY_PATH, formatted_data).is_err() {
+        exit!(
+            "Failed to write translation memory file. Ensure that the file permissions are set correctly."
+        );
+    }
+}
@@ -0,0 +1,479 @@
+use color_print::ceprintln;
+use reqwest::blocking::{Client, Response};
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+use crate::backend::{
+    Formality, RequestSettings, RetryPolicy, TranslationBackend, TranslationError,
+    TranslationRequest, Usage, journal,
+};
+use crate::exit;
+use crate::helper_functions::run_concurrently;
+use crate::types::Language;
+
+/// The maximum number of texts DeepL accepts in a single translation request.
+const MAX_TEXTS_PER_REQUEST: usize = 50;
+/// The maximum size of a translation request body DeepL accepts, in bytes.
+const MAX_REQUEST_BYTES: usize = 128 * 1024;
+/// Space left in each request body for parameters other than the texts, such as the languages.
+const REQUEST_OVERHEAD_BYTES: usize = 1024;
+/// The target languages which DeepL can translate more or less formally.
+pub const FORMALITY_LANGUAGES: &[&str] = &[
+    "DE", "ES", "ES-419", "FR", "IT", "JA", "NL", "PL", "PT-BR", "PT-PT", "RU",
+];
+/// The number of times to check whether a new glossary is ready to be used before giving up.
+const GLOSSARY_READY_CHECKS: u32 = 10;
+/// The status code DeepL responds with once the character quota has been used up.
+const QUOTA_EXCEEDED: u16 = 456;
+
+/// A [`TranslationBackend`] which uses the DeepL API.
+///
+/// Requests are sent directly instead of through a client library, so that errors can be told
+/// apart by their status code.
+pub struct DeepLBackend {
+    client: Client,
+    api_url: &'static str,
+    api_key: String,
+    available_source_langs: Vec<Language>,
+    available_target_langs: Vec<Language>,
+    retry_policy: RetryPolicy,
+    concurrency: usize,
+    request_limiter: RequestLimiter,
+}
+
+/// The response to a translation request.
+#[derive(Deserialize)]
+struct TranslationResponse {
+    translations: Vec<Translation>,
+}
+
+#[derive(Deserialize)]
+struct Translation {
+    text: String,
+}
+
+/// The response to a usage request, for the current billing period.
+#[derive(Deserialize)]
+struct UsageResponse {
+    character_count: u64,
+    character_limit: u64,
+}
+
+/// A single language in the response to a languages request.
+#[derive(Deserialize)]
+struct LanguageResponse {
+    language: String,
+    name: String,
+}
+
+/// The details of a glossary, as returned when it is created or requested.
+#[derive(Deserialize)]
+struct GlossaryResponse {
+    glossary_id: String,
+    ready: bool,
+}
+
+/// The body DeepL sends with most unsuccessful responses.
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+/// A semaphore which limits the number of requests in progress at once, across every language and
+/// batch being translated.
+struct RequestLimiter {
+    in_progress: Mutex<usize>,
+    limit: usize,
+    slot_freed: Condvar,
+}
+
+impl DeepLBackend {
+    /// Connect to the DeepL API using a key specified by the `DEEPL_API_KEY` environment variable.
+    ///
+    /// Every request, including those made while connecting, is retried according to
+    /// [`RequestSettings::retry_policy`] if it fails because of a temporary problem.
+    pub fn connect(settings: RequestSettings) -> Self {
+        let retry_policy = settings.retry_policy;
+        let Ok(deepl_api_key) = std::env::var("DEEPL_API_KEY") else {
+            exit!(
+                "DeepL API key was not found. Set it using the DEEPL_API_KEY environment variable, which can be specified in the .env file if you prefer."
+            );
+        };
+
+        // Keys for the free API end in ":fx", and must be used with its own host.
+        let mut backend = DeepLBackend {
+            client: Client::new(),
+            api_url: match deepl_api_key.ends_with(":fx") {
+                true => "https://api-free.deepl.com/v2",
+                false => "https://api.deepl.com/v2",
+            },
+            api_key: deepl_api_key,
+            available_source_langs: Vec::new(),
+            available_target_langs: Vec::new(),
+            retry_policy,
+            concurrency: settings.concurrency,
+            request_limiter: RequestLimiter {
+                in_progress: Mutex::new(0),
+                limit: settings.concurrency.max(1),
+                slot_freed: Condvar::new(),
+            },
+        };
+
+        if let Err(e) = backend.usage() {
+            match e {
+                TranslationError::Authorization => exit!(
+                    "Provided DeepL API key is invalid. Check the DEEPL_API_KEY environment variable."
+                ),
+                _ => exit!("Failed to connect to DeepL: {}.", e),
+            }
+        }
+
+        backend.available_source_langs = backend
+            .languages("source")
+            .unwrap_or_else(|e| exit!("Failed to fetch available source languages: {}.", e));
+        backend.available_target_langs = backend
+            .languages("target")
+            .unwrap_or_else(|e| exit!("Failed to fetch available target languages: {}.", e));
+
+        backend
+    }
+
+    /// Send a request to an endpoint of the API, returning the response whatever its status.
+    ///
+    /// The parameters are sent in the query string of `GET` requests, and as a form otherwise.
+    fn send(
+        &self,
+        method: Method,
+        endpoint: &str,
+        params: &[(&str, String)],
+    ) -> Result<Response, TranslationError> {
+        let request = self
+            .client
+            .request(method.clone(), format!("{}{endpoint}", self.api_url))
+            .header("Authorization", format!("DeepL-Auth-Key {}", self.api_key));
+        let request = match method {
+            Method::GET => request.query(params),
+            _ => request.form(params),
+        };
+
+        request
+            .send()
+            .map_err(|e| TranslationError::Unreachable(e.to_string()))
+    }
+
+    /// Send a request to an endpoint of the API, retrying it according to the retry policy, and
+    /// parse the body of its response.
+    fn call<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        params: &[(&str, String)],
+    ) -> Result<T, TranslationError> {
+        self.retry_policy.run(|| {
+            let response = check_status(self.send(method.clone(), endpoint, params)?)?;
+            response.json::<T>().map_err(|e| {
+                TranslationError::Request(format!("unexpected response from DeepL ({e})"))
+            })
+        })
+    }
+
+    /// Get the source or target languages, depending on `language_type`.
+    fn languages(&self, language_type: &str) -> Result<Vec<Language>, TranslationError> {
+        let languages = self.call::<Vec<LanguageResponse>>(
+            Method::GET,
+            "/languages",
+            &[("type", language_type.to_owned())],
+        )?;
+
+        Ok(languages
+            .into_iter()
+            .map(|l| Language {
+                code: l.language,
+                name: l.name,
+            })
+            .collect())
+    }
+
+    /// Split texts into batches which fit within DeepL's limits on the number of texts and the size
+    /// of each request.
+    ///
+    /// A single text which is too large on its own is still given its own batch, so that DeepL can
+    /// report the error. `context_bytes` is the size of the context sent with every batch.
+    fn batches(texts: &[String], context_bytes: usize) -> Vec<&[String]> {
+        let overhead_bytes = REQUEST_OVERHEAD_BYTES + context_bytes;
+        let mut batches = Vec::new();
+        let mut batch_start = 0;
+        let mut batch_bytes = overhead_bytes;
+        for (i, text) in texts.iter().enumerate() {
+            let text_bytes = Self::encoded_len(text);
+            let batch_len = i - batch_start;
+            if batch_len > 0
+                && (batch_len == MAX_TEXTS_PER_REQUEST
+                    || batch_bytes + text_bytes > MAX_REQUEST_BYTES)
+            {
+                batches.push(&texts[batch_start..i]);
+                batch_start = i;
+                batch_bytes = overhead_bytes;
+            }
+
+            batch_bytes += text_bytes;
+        }
+
+        if batch_start < texts.len() {
+            batches.push(&texts[batch_start..]);
+        }
+
+        batches
+    }
+
+    /// Get the size of a text once it is encoded as a `text` parameter in a form request body.
+    fn encoded_len(text: &str) -> usize {
+        let value_len = text
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' | b' ' => 1,
+                _ => 3,
+            })
+            .sum::<usize>();
+
+        "&text=".len() + value_len
+    }
+
+    /// Translate a single batch of texts, without retrying it.
+    fn translate_batch(
+        &self,
+        request: &TranslationRequest,
+        texts: &[String],
+    ) -> Result<Vec<String>, TranslationError> {
+        let mut params = vec![
+            ("source_lang", request.source_language.to_owned()),
+            ("target_lang", request.target_language.to_owned()),
+            ("preserve_formatting", "1".to_owned()),
+        ];
+        match request.formality {
+            Formality::Default => (),
+            Formality::More | Formality::PreferMore => {
+                params.push(("formality", "more".to_owned()))
+            }
+            Formality::Less | Formality::PreferLess => {
+                params.push(("formality", "less".to_owned()))
+            }
+        }
+
+        if let Some(glossary_id) = request.glossary_id {
+            params.push(("glossary_id", glossary_id.to_owned()));
+        }
+
+        if let Some(context) = request.context {
+            params.push(("context", context.to_owned()));
+        }
+
+        params.extend(texts.iter().map(|t| ("text", t.clone())));
+
+        let response = check_status(self.send(Method::POST, "/translate", &params)?)?
+            .json::<TranslationResponse>()
+            .map_err(|e| {
+                TranslationError::Request(format!("unexpected response from DeepL ({e})"))
+            })?;
+
+        Ok(response.translations.into_iter().map(|t| t.text).collect())
+    }
+}
+
+impl TranslationBackend for DeepLBackend {
+    fn name(&self) -> &str {
+        "DeepL"
+    }
+
+    fn identifier(&self) -> &str {
+        "deepl"
+    }
+
+    fn source_languages(&self) -> &[Language] {
+        &self.available_source_langs
+    }
+
+    fn target_languages(&self) -> &[Language] {
+        &self.available_target_langs
+    }
+
+    fn translate(&self, request: TranslationRequest) -> Result<Vec<String>, TranslationError> {
+        let context_bytes = request.context.map_or(0, Self::encoded_len);
+        let batches = Self::batches(request.texts, context_bytes)
+            .into_iter()
+            .enumerate()
+            .collect::<Vec<_>>();
+        let batch_count = batches.len();
+        let translated_batches = run_concurrently(&batches, self.concurrency, |(i, batch)| {
+            if journal::is_cancelled() {
+                return Err(TranslationError::Cancelled);
+            }
+
+            if batch_count > 1 {
+                ceprintln!(
+                    "Translating batch <c>{}</> of <c>{}</> for locale <g>'{}'</>...",
+                    i + 1,
+                    batch_count,
+                    request.target_language
+                );
+            }
+
+            let translated_batch = self.retry_policy.run(|| {
+                self.request_limiter
+                    .run(|| self.translate_batch(&request, batch))
+            })?;
+
+            if translated_batch.len() != batch.len() {
+                return Err(TranslationError::MismatchedResponse {
+                    expected: batch.len(),
+                    received: translated_batch.len(),
+                });
+            }
+
+            if let Some(on_batch) = request.on_batch {
+                on_batch(batch, &translated_batch);
+            }
+
+            Ok(translated_batch)
+        });
+
+        let mut translations = Vec::with_capacity(request.texts.len());
+        for translated_batch in translated_batches {
+            translations.extend(translated_batch?);
+        }
+
+        Ok(translations)
+    }
+
+    fn usage(&self) -> Result<Usage, TranslationError> {
+        let usage = self.call::<UsageResponse>(Method::GET, "/usage", &[])?;
+
+        Ok(Usage {
+            character_count: usage.character_count,
+            character_limit: usage.character_limit,
+        })
+    }
+
+    fn supports_formality(&self, language_code: &str) -> bool {
+        FORMALITY_LANGUAGES
+            .iter()
+            .any(|l| l.eq_ignore_ascii_case(language_code))
+    }
+
+    /// Create a DeepL glossary, waiting until it is ready to be used.
+    ///
+    /// DeepL only accepts glossaries between base languages, such as `EN` and `DE`, which are then
+    /// used for every variant of the target language.
+    fn create_glossary(
+        &self,
+        name: &str,
+        source_language: &str,
+        target_language: &str,
+        entries: &[(String, String)],
+    ) -> Result<String, TranslationError> {
+        let entries = entries
+            .iter()
+            .map(|(source, target)| format!("{source}\t{target}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let glossary = self.call::<GlossaryResponse>(
+            Method::POST,
+            "/glossaries",
+            &[
+                ("name", name.to_owned()),
+                ("source_lang", source_language.to_owned()),
+                ("target_lang", target_language.to_owned()),
+                ("entries", entries),
+                ("entries_format", "tsv".to_owned()),
+            ],
+        )?;
+
+        let mut ready = glossary.ready;
+        for _ in 0..GLOSSARY_READY_CHECKS {
+            if ready {
+                break;
+            }
+
+            std::thread::sleep(Duration::from_secs(1));
+            ready = self
+                .call::<GlossaryResponse>(
+                    Method::GET,
+                    &format!("/glossaries/{}", glossary.glossary_id),
+                    &[],
+                )?
+                .ready;
+        }
+
+        if !ready {
+            ceprintln!(
+                "<y>Glossary</> <g>'{}'</> <y>is not ready yet.</> Translations may fail until DeepL has finished creating it.",
+                name
+            );
+        }
+
+        Ok(glossary.glossary_id)
+    }
+
+    fn delete_glossary(&self, glossary_id: &str) -> Result<(), TranslationError> {
+        self.retry_policy.run(|| {
+            let response = self.send(Method::DELETE, &format!("/glossaries/{glossary_id}"), &[])?;
+            match response.status() {
+                StatusCode::NOT_FOUND => Ok(()),
+                _ => check_status(response).map(|_| ()),
+            }
+        })
+    }
+}
+
+impl RequestLimiter {
+    /// Run a request once fewer than [`Self::limit`] other requests are in progress.
+    fn run<T>(&self, request: impl FnOnce() -> T) -> T {
+        let mut in_progress = self.in_progress.lock().unwrap();
+        while *in_progress >= self.limit {
+            in_progress = self.slot_freed.wait(in_progress).unwrap();
+        }
+
+        *in_progress += 1;
+        drop(in_progress);
+
+        let _slot = RequestSlot { limiter: self };
+        request()
+    }
+}
+
+/// A request in progress, which frees its slot in the [`RequestLimiter`] when dropped, even if the
+/// request panics.
+struct RequestSlot<'a> {
+    limiter: &'a RequestLimiter,
+}
+
+impl Drop for RequestSlot<'_> {
+    fn drop(&mut self) {
+        *self.limiter.in_progress.lock().unwrap() -= 1;
+        self.limiter.slot_freed.notify_one();
+    }
+}
+
+/// Turn an unsuccessful response into the error for its status code.
+fn check_status(response: Response) -> Result<Response, TranslationError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let message = response
+        .json::<ErrorResponse>()
+        .map(|e| e.message)
+        .unwrap_or_else(|_| status.to_string());
+    Err(match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => TranslationError::Authorization,
+        StatusCode::TOO_MANY_REQUESTS => TranslationError::RateLimited,
+        _ if status.as_u16() == QUOTA_EXCEEDED => TranslationError::QuotaExceeded,
+        _ if status.is_server_error() => TranslationError::Unavailable(message),
+        _ => TranslationError::Request(format!("{status}: {message}")),
+    })
+}
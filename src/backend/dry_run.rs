@@ -0,0 +1,67 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::backend::{
+    PseudoBackend, TranslationBackend, TranslationError, TranslationRequest, Usage,
+};
+use crate::types::Language;
+
+/// A [`TranslationBackend`] used by `--dry-run`, which counts the characters it would be sent
+/// without contacting any external service.
+///
+/// Texts are returned untranslated, so that the rest of the translation pipeline (placeholder
+/// checks, ICU messages, and so on) runs exactly as it would for a real backend. The available
+/// languages are those of [`PseudoBackend`], which mirror DeepL.
+pub struct DryRunBackend {
+    languages: PseudoBackend,
+    character_count: AtomicU64,
+    identifier: &'static str,
+}
+
+impl DryRunBackend {
+    /// Create a dry run backend standing in for the backend with a given identifier, so that the
+    /// same glossaries and translation memory entries are used.
+    pub fn new(identifier: &'static str) -> Self {
+        DryRunBackend {
+            languages: PseudoBackend::new(),
+            character_count: AtomicU64::new(0),
+            identifier,
+        }
+    }
+}
+
+impl TranslationBackend for DryRunBackend {
+    fn name(&self) -> &str {
+        "dry run"
+    }
+
+    fn identifier(&self) -> &str {
+        self.identifier
+    }
+
+    fn source_languages(&self) -> &[Language] {
+        self.languages.source_languages()
+    }
+
+    fn target_languages(&self) -> &[Language] {
+        self.languages.target_languages()
+    }
+
+    fn translate(&self, request: TranslationRequest) -> Result<Vec<String>, TranslationError> {
+        let billed_characters = request.texts.iter().map(|t| t.chars().count() as u64).sum();
+        self.character_count
+            .fetch_add(billed_characters, Ordering::Relaxed);
+
+        Ok(request.texts.to_vec())
+    }
+
+    fn usage(&self) -> Result<Usage, TranslationError> {
+        Ok(Usage {
+            character_count: self.character_count.load(Ordering::Relaxed),
+            character_limit: u64::MAX,
+        })
+    }
+
+    fn supports_formality(&self, language_code: &str) -> bool {
+        self.languages.supports_formality(language_code)
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+use std::io::IsTerminal;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use color_print::ceprintln;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{TranslationBackend, TranslationError, TranslationRequest, Usage};
+use crate::helper_functions::create_directory_if_not_exists;
+use crate::types::Language;
+use crate::{APP_DIR_PATH, JOURNAL_PATH, exit, interact};
+
+/// Set once the user has asked for the current translations to stop, using Ctrl-C.
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+/// The number of journaled translation requests which are currently in progress.
+static IN_PROGRESS: AtomicUsize = AtomicUsize::new(0);
+/// Set once the journal has been deleted, so that no unsaved translations are written afterwards.
+static FINISHED: AtomicBool = AtomicBool::new(false);
+/// The commands which offer to resume an interrupted run.
+const RESUMABLE_COMMANDS: [&str; 2] = ["project setup", "project update"];
+/// How long finished batches may go unsaved. The whole journal is rewritten each time it is saved,
+/// so saving it after every batch would slow down large runs.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A record of the translations finished by a project command, kept until the command completes so
+/// that an interrupted run can be resumed without paying for the same translations again.
+#[derive(Default, Serialize, Deserialize)]
+struct Journal {
+    command: String,
+    backend: String,
+    /// Finished translations, grouped by language pair (such as `EN:DE`) and the options they were
+    /// translated with, then by source text.
+    translations: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// A [`TranslationBackend`] which records every batch translated by the inner backend in the
+/// journal, and serves texts which are already in the journal without sending them again.
+pub struct JournaledBackend {
+    inner: Box<dyn TranslationBackend>,
+    journal: Mutex<Journal>,
+    /// When the oldest batch which has not been saved yet was recorded.
+    unsaved_since: Mutex<Option<Instant>>,
+    /// Set once a request has failed, after which every batch is saved straight away.
+    failed: AtomicBool,
+}
+
+impl JournaledBackend {
+    /// Wrap a backend for a run of `command`, reusing the translations in the journal if a previous
+    /// run using the same backend was interrupted.
+    ///
+    /// Only the commands in [`RESUMABLE_COMMANDS`] ask before resuming. Other commands, which only
+    /// translate when languages are added, reuse the translations without asking.
+    pub fn new(inner: Box<dyn TranslationBackend>, backend: &str, command: &str) -> Self {
+        let fresh_journal = Journal {
+            command: command.to_owned(),
+            backend: backend.to_owned(),
+            ..Default::default()
+        };
+
+        let journal = match read_journal() {
+            Some(journal)
+                if journal.backend == backend
+                    && (!RESUMABLE_COMMANDS.contains(&command) || offer_resume(&journal)) =>
+            {
+                Journal {
+                    command: command.to_owned(),
+                    ..journal
+                }
+            }
+            _ => fresh_journal,
+        };
+
+        JournaledBackend {
+            inner,
+            journal: Mutex::new(journal),
+            unsaved_since: Mutex::new(None),
+            failed: AtomicBool::new(false),
+        }
+    }
+
+    /// Record a batch of finished translations, saving the journal if batches have gone unsaved
+    /// for [`SAVE_INTERVAL`]. Once the user has asked to stop or a request has failed, the command
+    /// is about to exit, so the journal is saved straight away.
+    fn record(&self, pair: &str, texts: &[String], translations: &[String]) {
+        let mut journal = self.journal.lock().unwrap();
+        journal
+            .translations
+            .entry(pair.to_owned())
+            .or_default()
+            .extend(texts.iter().cloned().zip(translations.iter().cloned()));
+
+        let mut unsaved_since = self.unsaved_since.lock().unwrap();
+        let oldest_unsaved = *unsaved_since.get_or_insert_with(Instant::now);
+        if is_cancelled()
+            || self.failed.load(Ordering::SeqCst)
+            || oldest_unsaved.elapsed() >= SAVE_INTERVAL
+        {
+            write_journal(&journal);
+            *unsaved_since = None;
+        }
+    }
+
+    /// Save the journal if any recorded batches have not been saved yet.
+    fn flush(&self) {
+        let journal = self.journal.lock().unwrap();
+        if self.unsaved_since.lock().unwrap().take().is_some() {
+            write_journal(&journal);
+        }
+    }
+
+    /// Note that a request has failed, saving everything recorded so far before the error is
+    /// reported.
+    fn fail(&self) {
+        self.failed.store(true, Ordering::SeqCst);
+        self.flush();
+    }
+}
+
+impl Drop for JournaledBackend {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl TranslationBackend for JournaledBackend {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn identifier(&self) -> &str {
+        self.inner.identifier()
+    }
+
+    fn source_languages(&self) -> &[Language] {
+        self.inner.source_languages()
+    }
+
+    fn target_languages(&self) -> &[Language] {
+        self.inner.target_languages()
+    }
+
+    fn translate(&self, request: TranslationRequest) -> Result<Vec<String>, TranslationError> {
+        IN_PROGRESS.fetch_add(1, Ordering::SeqCst);
+        let result = self.translate_journaled(request);
+        IN_PROGRESS.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    fn usage(&self) -> Result<Usage, TranslationError> {
+        self.inner.usage()
+    }
+
+    fn supports_formality(&self, language_code: &str) -> bool {
+        self.inner.supports_formality(language_code)
+    }
+}
+
+impl JournaledBackend {
+    fn translate_journaled(
+        &self,
+        request: TranslationRequest,
+    ) -> Result<Vec<String>, TranslationError> {
+        if is_cancelled() {
+            return Err(TranslationError::Cancelled);
+        }
+
+        let pair = format!(
+            "{}:{}{}",
+            request.source_language,
+            request.target_language,
+            request.options_key()
+        );
+        let mut translations = {
+            let journal = self.journal.lock().unwrap();
+            let finished = journal.translations.get(&pair);
+            request
+                .texts
+                .iter()
+                .map(|t| finished.and_then(|f| f.get(t)).cloned())
+                .collect::<Vec<_>>()
+        };
+
+        let remaining = request
+            .texts
+            .iter()
+            .zip(&translations)
+            .filter(|(_, finished)| finished.is_none())
+            .map(|(t, _)| t.clone())
+            .collect::<Vec<_>>();
+
+        if !remaining.is_empty() {
+            let record_batch =
+                |texts: &[String], translated: &[String]| self.record(&pair, texts, translated);
+            let translated_remaining = self
+                .inner
+                .translate(TranslationRequest {
+                    source_language: request.source_language,
+                    target_language: request.target_language,
+                    texts: &remaining,
+                    glossary_id: request.glossary_id,
+                    formality: request.formality,
+                    context: request.context,
+                    on_batch: Some(&record_batch),
+                })
+                .inspect_err(|_| self.fail())?;
+
+            // Backends which do not split requests into batches never call `on_batch`.
+            self.record(&pair, &remaining, &translated_remaining);
+
+            let mut translated_remaining = translated_remaining.into_iter();
+            for translation in translations.iter_mut().filter(|t| t.is_none()) {
+                *translation = translated_remaining.next();
+            }
+        }
+
+        Ok(translations.into_iter().flatten().collect())
+    }
+}
+
+/// Ask for journaled translations to stop at the next batch boundary, returning `false` if no
+/// journaled translations are in progress or a stop has already been requested, in which case the
+/// caller should exit immediately instead.
+pub fn request_cancel() -> bool {
+    IN_PROGRESS.load(Ordering::SeqCst) > 0 && !CANCELLED.swap(true, Ordering::SeqCst)
+}
+
+/// Check whether the user has asked for translations to stop.
+pub fn is_cancelled() -> bool {
+    CANCELLED.load(Ordering::SeqCst)
+}
+
+/// Delete the journal once the command it belongs to has completed.
+pub fn finish() {
+    FINISHED.store(true, Ordering::SeqCst);
+    let _ = std::fs::remove_file(JOURNAL_PATH);
+}
+
+/// Ask the user whether to resume from an interrupted run. The run is always resumed if there is no
+/// terminal to ask with, as reusing finished translations is always safe.
+fn offer_resume(journal: &Journal) -> bool {
+    let translation_count = journal
+        .translations
+        .values()
+        .map(|t| t.len())
+        .sum::<usize>();
+    let resume = !std::io::stdin().is_terminal()
+        || interact::confirm_prompt(&format!(
+            "A previous run of 'ltranslate {}' was interrupted after translating {} values. Resume it, reusing those translations?",
+            journal.command, translation_count
+        ));
+
+    if resume {
+        ceprintln!(
+            "Resuming with <c>{}</> translations from the journal.",
+            translation_count
+        );
+    }
+
+    resume
+}
+
+fn read_journal() -> Option<Journal> {
+    let journal = std::fs::read_to_string(JOURNAL_PATH).ok()?;
+    let Ok(journal) = serde_json::from_str(&journal) else {
+        ceprintln!(
+            "<y>Ignoring unreadable journal file</> <g>'{}'</>.",
+            JOURNAL_PATH
+        );
+        return None;
+    };
+
+    Some(journal)
+}
+
+/// Write the journal to a temporary file and move it into place, so that an interrupted write
+/// never leaves a truncated journal behind.
+fn write_journal(journal: &Journal) {
+    if FINISHED.load(Ordering::SeqCst) {
+        return;
+    }
+
+    let Ok(formatted_data) = serde_json::to_string(journal) else {
+        exit!("Unknown error occured when serializing journal.");
+    };
+
+    create_directory_if_not_exists(APP_DIR_PATH);
+    let temporary_path = format!("{JOURNAL_PATH}.tmp");
+    if std::fs::write(&temporary_path, formatted_data).is_err()
+        || std::fs::rename(&temporary_path, JOURNAL_PATH).is_err()
+    {
+        exit!("Failed to write journal file. Ensure that the file permissions are set correctly.");
+    }
+}
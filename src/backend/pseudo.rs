@@ -0,0 +1,249 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::backend::deepl::FORMALITY_LANGUAGES;
+use crate::backend::{TranslationBackend, TranslationError, TranslationRequest, Usage};
+use crate::types::Language;
+
+/// The proportion of the original text length which is appended as padding, to simulate languages
+/// that are more verbose than the source language.
+const PADDING_RATIO: f64 = 0.4;
+
+/// Source language codes and names offered by the pseudo-localization backend. These mirror the
+/// source languages supported by DeepL, so that projects can switch between backends freely.
+const PSEUDO_SOURCE_LANGUAGES: &[(&str, &str)] = &[
+    ("AR", "Arabic"),
+    ("BG", "Bulgarian"),
+    ("CS", "Czech"),
+    ("DA", "Danish"),
+    ("DE", "German"),
+    ("EL", "Greek"),
+    ("EN", "English"),
+    ("ES", "Spanish"),
+    ("ET", "Estonian"),
+    ("FI", "Finnish"),
+    ("FR", "French"),
+    ("HE", "Hebrew"),
+    ("HU", "Hungarian"),
+    ("ID", "Indonesian"),
+    ("IT", "Italian"),
+    ("JA", "Japanese"),
+    ("KO", "Korean"),
+    ("LT", "Lithuanian"),
+    ("LV", "Latvian"),
+    ("NB", "Norwegian Bokmål"),
+    ("NL", "Dutch"),
+    ("PL", "Polish"),
+    ("PT", "Portuguese"),
+    ("RO", "Romanian"),
+    ("RU", "Russian"),
+    ("SK", "Slovak"),
+    ("SL", "Slovenian"),
+    ("SV", "Swedish"),
+    ("TH", "Thai"),
+    ("TR", "Turkish"),
+    ("UK", "Ukrainian"),
+    ("VI", "Vietnamese"),
+    ("ZH", "Chinese"),
+];
+
+/// Target language codes and names offered by the pseudo-localization backend, mirroring DeepL as
+/// with [`PSEUDO_SOURCE_LANGUAGES`].
+const PSEUDO_TARGET_LANGUAGES: &[(&str, &str)] = &[
+    ("AR", "Arabic"),
+    ("BG", "Bulgarian"),
+    ("CS", "Czech"),
+    ("DA", "Danish"),
+    ("DE", "German"),
+    ("EL", "Greek"),
+    ("EN-GB", "English (British)"),
+    ("EN-US", "English (American)"),
+    ("ES", "Spanish"),
+    ("ES-419", "Spanish (Latin American)"),
+    ("ET", "Estonian"),
+    ("FI", "Finnish"),
+    ("FR", "French"),
+    ("HE", "Hebrew"),
+    ("HU", "Hungarian"),
+    ("ID", "Indonesian"),
+    ("IT", "Italian"),
+    ("JA", "Japanese"),
+    ("KO", "Korean"),
+    ("LT", "Lithuanian"),
+    ("LV", "Latvian"),
+    ("NB", "Norwegian Bokmål"),
+    ("NL", "Dutch"),
+    ("PL", "Polish"),
+    ("PT-BR", "Portuguese (Brazilian)"),
+    ("PT-PT", "Portuguese (European)"),
+    ("RO", "Romanian"),
+    ("RU", "Russian"),
+    ("SK", "Slovak"),
+    ("SL", "Slovenian"),
+    ("SV", "Swedish"),
+    ("TH", "Thai"),
+    ("TR", "Turkish"),
+    ("UK", "Ukrainian"),
+    ("VI", "Vietnamese"),
+    ("ZH-HANS", "Chinese (simplified)"),
+    ("ZH-HANT", "Chinese (traditional)"),
+];
+
+/// A [`TranslationBackend`] which produces deterministic pseudo-translations without contacting
+/// any external service.
+///
+/// Each translated value has its letters replaced by accented equivalents, is padded to simulate
+/// longer languages, and is wrapped in brackets so that untranslated or truncated strings are easy
+/// to spot in a UI. Anything inside `{...}` or `<...>` is left untouched so that placeholders and
+/// markup survive.
+pub struct PseudoBackend {
+    available_source_langs: Vec<Language>,
+    available_target_langs: Vec<Language>,
+    character_count: AtomicU64,
+}
+
+impl PseudoBackend {
+    pub fn new() -> Self {
+        PseudoBackend {
+            available_source_langs: Self::convert_languages(PSEUDO_SOURCE_LANGUAGES),
+            available_target_langs: Self::convert_languages(PSEUDO_TARGET_LANGUAGES),
+            character_count: AtomicU64::new(0),
+        }
+    }
+
+    fn convert_languages(languages: &[(&str, &str)]) -> Vec<Language> {
+        languages
+            .iter()
+            .map(|(code, name)| Language {
+                code: (*code).to_owned(),
+                name: (*name).to_owned(),
+            })
+            .collect()
+    }
+
+    /// Pseudo-translate a single value.
+    fn pseudo_translate(text: &str) -> String {
+        let mut translated = String::with_capacity(text.len() * 2);
+        let mut letter_count = 0;
+        let mut closing_delimiter = None;
+
+        for c in text.chars() {
+            match closing_delimiter {
+                Some(closing) => {
+                    translated.push(c);
+                    if c == closing {
+                        closing_delimiter = None;
+                    }
+                }
+                None => {
+                    closing_delimiter = match c {
+                        '{' => Some('}'),
+                        '<' => Some('>'),
+                        _ => None,
+                    };
+
+                    if c.is_alphanumeric() {
+                        letter_count += 1;
+                    }
+
+                    translated.push(Self::accent(c));
+                }
+            }
+        }
+
+        let padding = (letter_count as f64 * PADDING_RATIO).ceil() as usize;
+        format!("[{translated}{}]", " ~".repeat(padding.div_ceil(2)))
+    }
+
+    /// Replace an ASCII letter with a visually similar accented character.
+    fn accent(c: char) -> char {
+        match c {
+            'a' => 'à',
+            'c' => 'ç',
+            'e' => 'é',
+            'i' => 'ï',
+            'n' => 'ñ',
+            'o' => 'ô',
+            's' => 'š',
+            'u' => 'ü',
+            'y' => 'ý',
+            'z' => 'ž',
+            'A' => 'Å',
+            'C' => 'Ç',
+            'E' => 'É',
+            'I' => 'Î',
+            'N' => 'Ñ',
+            'O' => 'Ö',
+            'S' => 'Š',
+            'U' => 'Û',
+            'Y' => 'Ý',
+            'Z' => 'Ž',
+            _ => c,
+        }
+    }
+}
+
+impl TranslationBackend for PseudoBackend {
+    fn name(&self) -> &str {
+        "pseudo-localization"
+    }
+
+    fn identifier(&self) -> &str {
+        "pseudo"
+    }
+
+    fn source_languages(&self) -> &[Language] {
+        &self.available_source_langs
+    }
+
+    fn target_languages(&self) -> &[Language] {
+        &self.available_target_langs
+    }
+
+    fn translate(&self, request: TranslationRequest) -> Result<Vec<String>, TranslationError> {
+        let billed_characters = request.texts.iter().map(|t| t.chars().count() as u64).sum();
+        self.character_count
+            .fetch_add(billed_characters, Ordering::Relaxed);
+
+        Ok(request
+            .texts
+            .iter()
+            .map(|t| Self::pseudo_translate(t))
+            .collect())
+    }
+
+    fn usage(&self) -> Result<Usage, TranslationError> {
+        Ok(Usage {
+            character_count: self.character_count.load(Ordering::Relaxed),
+            character_limit: u64::MAX,
+        })
+    }
+
+    /// Pretend to create a glossary, so that glossary commands can be tried out offline. The
+    /// glossary is not applied to pseudo-translations.
+    fn create_glossary(
+        &self,
+        _name: &str,
+        source_language: &str,
+        target_language: &str,
+        entries: &[(String, String)],
+    ) -> Result<String, TranslationError> {
+        Ok(format!(
+            "pseudo-{}-{}-{}",
+            source_language.to_lowercase(),
+            target_language.to_lowercase(),
+            entries.len()
+        ))
+    }
+
+    fn delete_glossary(&self, _glossary_id: &str) -> Result<(), TranslationError> {
+        Ok(())
+    }
+
+    /// Formality is not applied to pseudo-translations, but the languages which support it mirror
+    /// DeepL, so that the same warnings are printed.
+    fn supports_formality(&self, language_code: &str) -> bool {
+        FORMALITY_LANGUAGES
+            .iter()
+            .any(|l| l.eq_ignore_ascii_case(language_code))
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use color_print::ceprintln;
+use serde::Serialize;
+
+use crate::key_path;
+use crate::locale_file::{self, LocaleFormat};
+use crate::lockfile::Lockfile;
+use crate::types::{FlatLocaleData, LocaleDataDiff, LocaleDocument, LocaleManifest};
+use crate::{LOCKFILE_PATH, exit};
+
+/// The result of checking every locale in a project against the current source locale, without
+/// translating anything.
+#[derive(Serialize)]
+pub struct CheckReport {
+    pub up_to_date: bool,
+    pub source: SourceReport,
+    pub locales: Vec<LocaleReport>,
+}
+
+/// Changes made to the source locale since translations were last updated, combined across every
+/// locale.
+#[derive(Serialize)]
+pub struct SourceReport {
+    pub path: PathBuf,
+    /// Keys whose current source value has not been translated into at least one locale.
+    pub changed_keys: Vec<String>,
+    /// Keys which have been removed from the source locale, but not yet from at least one locale.
+    pub removed_keys: Vec<String>,
+}
+
+/// The state of a single foreign locale file.
+#[derive(Serialize)]
+pub struct LocaleReport {
+    pub language: String,
+    pub path: PathBuf,
+    pub status: LocaleStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Keys whose source value has changed since the locale was last translated.
+    pub stale_keys: Vec<String>,
+    /// Keys in the source locale which are missing from this locale.
+    pub missing_keys: Vec<String>,
+    /// Keys in this locale which are not in the source locale.
+    pub extra_keys: Vec<String>,
+    /// Keys which were edited by hand, and whose source value has changed since.
+    pub review_keys: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocaleStatus {
+    UpToDate,
+    Stale,
+    NeedsReview,
+    MissingFile,
+    Unparsable,
+}
+
+impl CheckReport {
+    /// Check every locale listed in [`LocaleManifest::locale_paths`] against the source locale and
+    /// the lockfile.
+    pub fn new(manifest_data: &LocaleManifest) -> Self {
+        let Some(source_document) = LocaleDocument::source(manifest_data) else {
+            exit!(
+                "Missing source locale file <r>'{}'</>.",
+                manifest_data.source_locale_path.to_string_lossy()
+            );
+        };
+
+        let Some(mut lockfile) = Lockfile::read(manifest_data) else {
+            exit!(
+                "Missing lockfile <g>'{}'</>. Run <m>'ltranslate project setup'</> to install ltranslate into your project if necessary.",
+                LOCKFILE_PATH
+            );
+        };
+
+        let source_data = source_document.flat_data();
+        // Values edited by hand are found the same way as during an update, so that they are not
+        // reported as stale, but the lockfile is not written.
+        let diffs = manifest_data
+            .locale_paths
+            .iter()
+            .map(|(code, path)| {
+                if let Ok(locale_data) = read_locale(path, code) {
+                    lockfile.detect_edits(code, &locale_data);
+                }
+
+                lockfile.diff(code, &source_data)
+            })
+            .collect::<Vec<_>>();
+
+        let changed_keys = source_data
+            .keys()
+            .filter(|k| {
+                diffs
+                    .iter()
+                    .flatten()
+                    .any(|d| d.changed_or_added.contains_key(*k))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        let removed_keys = diffs
+            .iter()
+            .flatten()
+            .flat_map(|d| d.removed.iter().cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        let locales = manifest_data
+            .locale_paths
+            .iter()
+            .zip(&diffs)
+            .map(|((code, path), diff)| {
+                let mut review_keys = lockfile.review_keys(code);
+                if let Some(diff) = diff {
+                    review_keys.extend(diff.review.iter().cloned());
+                }
+
+                LocaleReport::new(code, path, &source_data, diff.as_ref(), review_keys)
+            })
+            .collect::<Vec<_>>();
+
+        CheckReport {
+            up_to_date: changed_keys.is_empty()
+                && removed_keys.is_empty()
+                && locales.iter().all(|l| l.status == LocaleStatus::UpToDate),
+            source: SourceReport {
+                path: manifest_data.source_locale_path.clone(),
+                changed_keys,
+                removed_keys,
+            },
+            locales,
+        }
+    }
+
+    /// Print the report as JSON to stdout.
+    pub fn print_json(&self) {
+        let Ok(report) = serde_json::to_string_pretty(self) else {
+            exit!("Unknown error occured when serializing check report.");
+        };
+
+        println!("{}", report);
+    }
+
+    /// Print a human-readable summary of the report to stderr.
+    pub fn print_summary(&self) {
+        if !self.source.changed_keys.is_empty() || !self.source.removed_keys.is_empty() {
+            ceprintln!(
+                "Source locale <g>'{}'</> has changed since the last update:",
+                self.source.path.to_string_lossy()
+            );
+            print_keys("changed or added", &self.source.changed_keys);
+            print_keys("removed", &self.source.removed_keys);
+        }
+
+        for locale in &self.locales {
+            match locale.status {
+                LocaleStatus::UpToDate => {
+                    ceprintln!("<g>'{}'</> is up to date.", locale.language)
+                }
+                LocaleStatus::Stale => {
+                    ceprintln!(
+                        "<y>'{}'</> is out of date ({}):",
+                        locale.language,
+                        locale.path.to_string_lossy()
+                    );
+                    print_keys("stale", &locale.stale_keys);
+                    print_keys("missing", &locale.missing_keys);
+                    print_keys("extra", &locale.extra_keys);
+                    print_keys("to review", &locale.review_keys);
+                }
+                LocaleStatus::NeedsReview => {
+                    ceprintln!(
+                        "<y>'{}'</> has values edited by hand whose source text has changed ({}):",
+                        locale.language,
+                        locale.path.to_string_lossy()
+                    );
+                    print_keys("to review", &locale.review_keys);
+                }
+                LocaleStatus::MissingFile | LocaleStatus::Unparsable => {
+                    ceprintln!(
+                        "<r>'{}'</> could not be read ({}): {}.",
+                        locale.language,
+                        locale.path.to_string_lossy(),
+                        locale.error.as_deref().unwrap_or("unknown error")
+                    );
+                }
+            }
+        }
+
+        if self.up_to_date {
+            eprintln!("All locales are up to date.");
+            return;
+        }
+
+        if self
+            .locales
+            .iter()
+            .any(|l| !matches!(l.status, LocaleStatus::UpToDate | LocaleStatus::NeedsReview))
+            || !self.source.changed_keys.is_empty()
+            || !self.source.removed_keys.is_empty()
+        {
+            ceprintln!(
+                "<r>Some locales are out of date.</> Run <m>'ltranslate project update'</> to update them."
+            );
+        }
+
+        if self.locales.iter().any(|l| !l.review_keys.is_empty()) {
+            ceprintln!(
+                "<r>Some values edited by hand need review.</> Run <m>'ltranslate project review'</> to keep or retranslate them."
+            );
+        }
+    }
+}
+
+impl LocaleReport {
+    fn new(
+        code: &str,
+        path: &Path,
+        source_data: &FlatLocaleData,
+        diff: Option<&LocaleDataDiff>,
+        review_keys: Vec<String>,
+    ) -> Self {
+        let mut report = LocaleReport {
+            language: code.to_owned(),
+            path: path.to_owned(),
+            status: LocaleStatus::UpToDate,
+            error: None,
+            stale_keys: Vec::new(),
+            missing_keys: Vec::new(),
+            extra_keys: Vec::new(),
+            review_keys,
+        };
+
+        let locale_data = match read_locale(path, code) {
+            Ok(locale_data) => locale_data,
+            Err((status, error)) => {
+                report.status = status;
+                report.error = Some(error);
+                return report;
+            }
+        };
+
+        if let Some(diff) = diff {
+            report.stale_keys = diff
+                .changed_or_added
+                .keys()
+                .filter(|k| locale_data.contains_key(*k))
+                .cloned()
+                .collect();
+        }
+
+        report.missing_keys = source_data
+            .keys()
+            .filter(|k| !locale_data.contains_key(*k))
+            .cloned()
+            .collect();
+        report.extra_keys = locale_data
+            .keys()
+            .filter(|k| !source_data.contains_key(*k))
+            .cloned()
+            .collect();
+
+        if !report.stale_keys.is_empty()
+            || !report.missing_keys.is_empty()
+            || !report.extra_keys.is_empty()
+        {
+            report.status = LocaleStatus::Stale;
+        } else if !report.review_keys.is_empty() {
+            report.status = LocaleStatus::NeedsReview;
+        }
+
+        report
+    }
+}
+
+/// Read and flatten a foreign locale file, reporting problems instead of exiting.
+fn read_locale(path: &Path, language_code: &str) -> Result<FlatLocaleData, (LocaleStatus, String)> {
+    let locale_data = std::fs::read_to_string(path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => (LocaleStatus::MissingFile, "file does not exist".to_owned()),
+        _ => (LocaleStatus::MissingFile, e.to_string()),
+    })?;
+
+    let (locale_data, _) =
+        locale_file::parse(&locale_data, LocaleFormat::of(path), language_code, false)
+            .map_err(|e| (LocaleStatus::Unparsable, e))?;
+
+    Ok(key_path::flatten(&locale_data))
+}
+
+/// Print a labeled, comma-separated list of key paths, if there are any.
+pub fn print_keys(label: &str, keys: &[String]) {
+    if keys.is_empty() {
+        return;
+    }
+
+    ceprintln!("  {} {}: {}", keys.len(), label, keys.join(", "));
+}
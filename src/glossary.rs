@@ -0,0 +1,275 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::helper_functions::{create_parent_directories_if_not_exists, hash_text};
+use crate::{GLOSSARY_DIR_PATH, exit};
+
+/// A list of terms which should always be translated the same way from one language into another,
+/// such as product names, stored in `ltranslate/glossaries/` as a TSV or CSV file named after its
+/// language pair (such as `EN-DE.tsv`).
+///
+/// Glossaries are made between base languages, so `EN-PT.tsv` applies to both `PT-BR` and `PT-PT`.
+pub struct Glossary {
+    pub source_language: String,
+    pub target_language: String,
+    entries: Vec<(String, String)>,
+    format: GlossaryFormat,
+}
+
+#[derive(Clone, Copy)]
+enum GlossaryFormat {
+    Tsv,
+    Csv,
+}
+
+/// The glossary on the translation service which a glossary file was last synced to.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SyncedGlossary {
+    /// The identifier of the backend the glossary was created with, as its ID means nothing to any
+    /// other backend.
+    #[serde(default)]
+    pub backend: String,
+    pub id: String,
+    /// A hash of the entries which were synced, used to tell whether the file has changed since.
+    pub entries_hash: String,
+}
+
+impl Glossary {
+    /// Read the glossary for a language pair, or start an empty one if it does not exist yet.
+    pub fn read(source_language: &str, target_language: &str) -> Self {
+        let source_language = glossary_language(source_language);
+        let target_language = glossary_language(target_language);
+        let name = pair_name(&source_language, &target_language);
+
+        for format in [GlossaryFormat::Tsv, GlossaryFormat::Csv] {
+            let path = Path::new(GLOSSARY_DIR_PATH).join(format!("{name}.{}", format.extension()));
+            if let Ok(data) = std::fs::read_to_string(&path) {
+                return Glossary {
+                    entries: parse_entries(&data, format, &path),
+                    source_language,
+                    target_language,
+                    format,
+                };
+            }
+        }
+
+        Glossary {
+            source_language,
+            target_language,
+            entries: Vec::new(),
+            format: GlossaryFormat::Tsv,
+        }
+    }
+
+    /// Read every glossary whose source language is `source_language`.
+    pub fn read_all(source_language: &str) -> Vec<Self> {
+        let source_language = glossary_language(source_language);
+        let Ok(files) = std::fs::read_dir(GLOSSARY_DIR_PATH) else {
+            return Vec::new();
+        };
+
+        let mut target_languages = files
+            .flatten()
+            .filter_map(|f| {
+                let path = f.path();
+                GlossaryFormat::from_path(&path)?;
+                let (source, target) = path.file_stem()?.to_str()?.split_once('-')?;
+                (glossary_language(source) == source_language).then(|| glossary_language(target))
+            })
+            .collect::<Vec<_>>();
+        target_languages.sort();
+        target_languages.dedup();
+
+        target_languages
+            .iter()
+            .map(|target| Glossary::read(&source_language, target))
+            .collect()
+    }
+
+    /// Get the name of the language pair, such as `EN-DE`, which is used as the file name and to
+    /// identify the glossary in the manifest.
+    pub fn name(&self) -> String {
+        pair_name(&self.source_language, &self.target_language)
+    }
+
+    pub fn path(&self) -> PathBuf {
+        Path::new(GLOSSARY_DIR_PATH).join(format!("{}.{}", self.name(), self.format.extension()))
+    }
+
+    pub fn entries(&self) -> &[(String, String)] {
+        &self.entries
+    }
+
+    /// Get a hash of the entries, used to tell whether they have changed since the glossary was
+    /// last synced.
+    pub fn entries_hash(&self) -> String {
+        let entries = self
+            .entries
+            .iter()
+            .map(|(source, target)| format!("{source}\t{target}\n"))
+            .collect::<String>();
+
+        hash_text(&entries)
+    }
+
+    /// Set the translation of a term, replacing any existing translation of it.
+    pub fn set(&mut self, source_term: &str, target_term: &str) {
+        let source_term = source_term.trim();
+        let target_term = target_term.trim();
+        if source_term.is_empty() || target_term.is_empty() {
+            exit!("Glossary terms cannot be empty.");
+        }
+
+        if source_term.contains(['\t', '\n', '\r']) || target_term.contains(['\t', '\n', '\r']) {
+            exit!("Glossary terms cannot contain tabs or line breaks.");
+        }
+
+        match self.entries.iter_mut().find(|(s, _)| s == source_term) {
+            Some((_, target)) => *target = target_term.to_owned(),
+            None => self
+                .entries
+                .push((source_term.to_owned(), target_term.to_owned())),
+        }
+    }
+
+    /// Remove a term, returning `false` if it was not in the glossary.
+    pub fn remove(&mut self, source_term: &str) -> bool {
+        let entry_count = self.entries.len();
+        self.entries.retain(|(s, _)| s != source_term.trim());
+        self.entries.len() != entry_count
+    }
+
+    /// Write the glossary to its file, in the same format it was read from.
+    pub fn write_out(&self) {
+        let data = self
+            .entries
+            .iter()
+            .map(|(source, target)| match self.format {
+                GlossaryFormat::Tsv => format!("{source}\t{target}\n"),
+                GlossaryFormat::Csv => format!("{},{}\n", csv_field(source), csv_field(target)),
+            })
+            .collect::<String>();
+
+        let path = self.path();
+        create_parent_directories_if_not_exists(&path);
+        if std::fs::write(&path, data).is_err() {
+            exit!(
+                "Failed to write glossary file <g>'{}'</>. Ensure that the file permissions are set correctly.",
+                path.to_string_lossy()
+            );
+        }
+    }
+}
+
+impl GlossaryFormat {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "tsv" => Some(GlossaryFormat::Tsv),
+            "csv" => Some(GlossaryFormat::Csv),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            GlossaryFormat::Tsv => "tsv",
+            GlossaryFormat::Csv => "csv",
+        }
+    }
+}
+
+/// Get the base language a glossary is made for, as glossaries cannot be made for regional
+/// variants such as `PT-BR`.
+pub fn glossary_language(language_code: &str) -> String {
+    language_code
+        .split('-')
+        .next()
+        .unwrap_or(language_code)
+        .to_uppercase()
+}
+
+/// Get the name of a glossary between two languages, such as `EN-DE`.
+pub fn pair_name(source_language: &str, target_language: &str) -> String {
+    format!(
+        "{}-{}",
+        glossary_language(source_language),
+        glossary_language(target_language)
+    )
+}
+
+/// Parse the term pairs of a glossary file, exiting if any line is malformed or any source term is
+/// listed twice. Blank lines are ignored.
+fn parse_entries(data: &str, format: GlossaryFormat, path: &Path) -> Vec<(String, String)> {
+    let mut entries: Vec<(String, String)> = Vec::new();
+    for (i, line) in data.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields = match format {
+            GlossaryFormat::Tsv => line.split('\t').map(str::to_owned).collect(),
+            GlossaryFormat::Csv => split_csv_line(line),
+        };
+
+        let [source, target] = fields.as_slice() else {
+            exit!(
+                "Line {} of glossary file <g>'{}'</> must contain exactly two terms.",
+                i + 1,
+                path.to_string_lossy()
+            );
+        };
+
+        let (source, target) = (source.trim(), target.trim());
+        if source.is_empty() || target.is_empty() {
+            exit!(
+                "Line {} of glossary file <g>'{}'</> contains an empty term.",
+                i + 1,
+                path.to_string_lossy()
+            );
+        }
+
+        if entries.iter().any(|(s, _)| s == source) {
+            exit!(
+                "Term <r>'{}'</> is listed more than once in glossary file <g>'{}'</>.",
+                source,
+                path.to_string_lossy()
+            );
+        }
+
+        entries.push((source.to_owned(), target.to_owned()));
+    }
+
+    entries
+}
+
+/// Split a line of a CSV file into its fields, which may be quoted with `"`, using `""` for a
+/// literal quote.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+
+    fields.push(field);
+    fields
+}
+
+/// Quote a CSV field if it contains characters which would otherwise be misread.
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_owned(),
+    }
+}
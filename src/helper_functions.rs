@@ -1,5 +1,8 @@
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use sha2::{Digest, Sha256};
 use soft_canonicalize::soft_canonicalize;
 
 use crate::exit;
@@ -43,3 +46,53 @@ pub fn create_parent_directories_if_not_exists(path: impl Into<PathBuf>) {
         );
     }
 }
+
+/// Hash a text as a hexadecimal SHA-256 digest, so that changes to it can be detected without
+/// storing the text itself.
+pub fn hash_text(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Run `task` on every item, using at most `concurrency` threads at once, and return the results in
+/// the same order as `items`.
+pub fn run_concurrently<T, R>(
+    items: &[T],
+    concurrency: usize,
+    task: impl Fn(&T) -> R + Sync,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    if concurrency <= 1 || items.len() <= 1 {
+        return items.iter().map(task).collect();
+    }
+
+    let next_index = AtomicUsize::new(0);
+    let results = Mutex::new(items.iter().map(|_| None).collect::<Vec<_>>());
+    std::thread::scope(|scope| {
+        for _ in 0..concurrency.min(items.len()) {
+            scope.spawn(|| {
+                loop {
+                    let i = next_index.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+
+                    let result = task(item);
+                    results.lock().unwrap()[i] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect()
+}
@@ -0,0 +1,859 @@
+use crate::placeholder::TextPart;
+
+/// Plural categories in the order CLDR lists them.
+const PLURAL_CATEGORIES: [&str; 6] = ["zero", "one", "two", "few", "many", "other"];
+
+/// An ICU MessageFormat message which contains at least one `plural`, `selectordinal`, or `select`
+/// argument.
+///
+/// Messages are split into literal text fragments which can be translated individually, while the
+/// surrounding ICU syntax is kept intact. Simple arguments such as `{name}` and the `#` symbol are
+/// kept inside the fragments as placeholders so that the translation engine can move them around.
+pub struct IcuMessage {
+    nodes: Vec<Node>,
+}
+
+enum Node {
+    Literal(String),
+    /// A simple or formatted argument such as `{name}` or `{amount, number, ::currency/EUR}`,
+    /// stored exactly as it appeared in the source.
+    Argument(String),
+    /// The `#` symbol inside a plural branch, which stands for the (offset) plural value.
+    Pound,
+    Branching {
+        name: String,
+        kind: BranchKind,
+        offset: Option<String>,
+        branches: Vec<Branch>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum BranchKind {
+    Plural,
+    SelectOrdinal,
+    Select,
+}
+
+struct Branch {
+    selector: String,
+    message: Vec<Node>,
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl IcuMessage {
+    /// Parse an ICU message, returning [`None`] if the text is not valid ICU syntax or does not
+    /// contain any branching arguments. Plain values are better served by regular placeholder
+    /// handling.
+    pub fn parse(text: &str) -> Option<Self> {
+        if !text.contains('{') {
+            return None;
+        }
+
+        let mut parser = Parser { text, position: 0 };
+        let nodes = parser.parse_message(false, false)?;
+        if parser.position != text.len() {
+            return None;
+        }
+
+        let message = IcuMessage { nodes };
+        message.has_branching().then_some(message)
+    }
+
+    /// Add or remove plural categories so that every `plural` and `selectordinal` argument has
+    /// exactly the categories used by a given target language, according to the CLDR plural rules.
+    ///
+    /// Added categories start out as copies of the `other` branch. Exact-value selectors such as
+    /// `=0` are always kept.
+    pub fn adapt_plurals(&mut self, language_code: &str) {
+        adapt_nodes(&mut self.nodes, language_code);
+    }
+
+    /// Get the translatable fragments of the message, in the order expected by
+    /// [`IcuMessage::render()`].
+    pub fn fragments(&self) -> Vec<Vec<TextPart>> {
+        let mut fragments = Vec::new();
+        collect_fragments(&self.nodes, &mut fragments);
+        fragments
+    }
+
+    /// Render the message back into ICU syntax, replacing each fragment returned by
+    /// [`IcuMessage::fragments()`] with its translation.
+    pub fn render(&self, translated_fragments: Vec<Vec<TextPart>>) -> String {
+        let mut translated_fragments = translated_fragments.into_iter();
+        let mut rendered = String::new();
+        render_nodes(&self.nodes, false, &mut translated_fragments, &mut rendered);
+        rendered
+    }
+
+    /// Build the text of a message made of a single `plural` argument from the text of each of its
+    /// categories, as used for the plural forms of gettext messages.
+    pub fn plural_text(forms: &[(&str, &str)]) -> String {
+        let branches = forms
+            .iter()
+            .map(|(category, text)| format!("{category} {{{}}}", escape_literal(text, true)))
+            .collect::<Vec<_>>();
+
+        format!("{{count, plural, {}}}", branches.join(" "))
+    }
+
+    /// Get the category and text of each branch of a message made of a single `plural` argument,
+    /// or [`None`] if the message is anything else. This is the reverse of
+    /// [`IcuMessage::plural_text()`].
+    pub fn plural_forms(text: &str) -> Option<Vec<(String, String)>> {
+        let message = IcuMessage::parse(text)?;
+        let [
+            Node::Branching {
+                kind: BranchKind::Plural,
+                offset: None,
+                branches,
+                ..
+            },
+        ] = message.nodes.as_slice()
+        else {
+            return None;
+        };
+
+        branches
+            .iter()
+            .map(|b| Some((b.selector.clone(), plain_text(&b.message)?)))
+            .collect()
+    }
+
+    fn has_branching(&self) -> bool {
+        self.nodes
+            .iter()
+            .any(|n| matches!(n, Node::Branching { .. }))
+    }
+}
+
+impl BranchKind {
+    fn from_identifier(identifier: &str) -> Option<Self> {
+        match identifier {
+            "plural" => Some(BranchKind::Plural),
+            "selectordinal" => Some(BranchKind::SelectOrdinal),
+            "select" => Some(BranchKind::Select),
+            _ => None,
+        }
+    }
+
+    fn identifier(self) -> &'static str {
+        match self {
+            BranchKind::Plural => "plural",
+            BranchKind::SelectOrdinal => "selectordinal",
+            BranchKind::Select => "select",
+        }
+    }
+
+    fn uses_pound(self) -> bool {
+        self != BranchKind::Select
+    }
+}
+
+impl Parser<'_> {
+    /// Parse a sequence of nodes until the end of the text, or until an unmatched `}` if `nested`
+    /// is set.
+    fn parse_message(&mut self, nested: bool, in_plural: bool) -> Option<Vec<Node>> {
+        let mut nodes = Vec::new();
+        let mut literal = String::new();
+
+        while let Some(c) = self.peek() {
+            match c {
+                '\'' => literal.push_str(&self.parse_quoted(in_plural)),
+                '{' => {
+                    push_literal(&mut nodes, &mut literal);
+                    nodes.push(self.parse_argument(in_plural)?);
+                }
+                '}' if nested => break,
+                '}' => return None,
+                '#' if in_plural => {
+                    self.advance();
+                    push_literal(&mut nodes, &mut literal);
+                    nodes.push(Node::Pound);
+                }
+                _ => {
+                    self.advance();
+                    literal.push(c);
+                }
+            }
+        }
+
+        push_literal(&mut nodes, &mut literal);
+        Some(nodes)
+    }
+
+    /// Parse text starting at an apostrophe, following the ICU rules: `''` is a literal
+    /// apostrophe, and an apostrophe followed by a syntax character starts a quoted section which
+    /// runs until the next single apostrophe. Any other apostrophe is literal.
+    fn parse_quoted(&mut self, in_plural: bool) -> String {
+        self.advance();
+        match self.peek() {
+            Some('\'') => {
+                self.advance();
+                "'".to_owned()
+            }
+            Some(c) if c == '{' || c == '}' || (c == '#' && in_plural) => {
+                let mut quoted = String::new();
+                while let Some(c) = self.advance() {
+                    if c != '\'' {
+                        quoted.push(c);
+                    } else if self.peek() == Some('\'') {
+                        self.advance();
+                        quoted.push('\'');
+                    } else {
+                        break;
+                    }
+                }
+
+                quoted
+            }
+            _ => "'".to_owned(),
+        }
+    }
+
+    fn parse_argument(&mut self, in_plural: bool) -> Option<Node> {
+        let start = self.position;
+        self.expect('{')?;
+        self.skip_whitespace();
+        let name = self.parse_word()?;
+        self.skip_whitespace();
+
+        if self.consume('}') {
+            return Some(Node::Argument(self.text[start..self.position].to_owned()));
+        }
+
+        self.expect(',')?;
+        self.skip_whitespace();
+        let argument_type = self.parse_word()?;
+        self.skip_whitespace();
+
+        let Some(kind) = BranchKind::from_identifier(&argument_type) else {
+            // Formatted arguments such as `{n, number}` or `{d, date, short}` are kept verbatim.
+            self.skip_balanced()?;
+            return Some(Node::Argument(self.text[start..self.position].to_owned()));
+        };
+
+        self.expect(',')?;
+        let mut offset = None;
+        let mut branches = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.consume('}') {
+                break;
+            }
+
+            let selector = self.parse_word()?;
+            if let Some(offset_value) = selector.strip_prefix("offset:") {
+                offset = Some(offset_value.to_owned());
+                continue;
+            }
+
+            self.skip_whitespace();
+            self.expect('{')?;
+            let message = self.parse_message(true, in_plural || kind.uses_pound())?;
+            self.expect('}')?;
+            branches.push(Branch { selector, message });
+        }
+
+        if branches.is_empty() {
+            return None;
+        }
+
+        Some(Node::Branching {
+            name,
+            kind,
+            offset,
+            branches,
+        })
+    }
+
+    /// Skip the remainder of a formatted argument up to and including its closing brace.
+    fn skip_balanced(&mut self) -> Option<()> {
+        let mut depth = 1;
+        while let Some(c) = self.advance() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    fn parse_word(&mut self) -> Option<String> {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || matches!(c, '{' | '}' | ',') {
+                break;
+            }
+
+            self.advance();
+        }
+
+        (self.position > start).then(|| self.text[start..self.position].to_owned())
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.advance();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Option<()> {
+        self.consume(expected).then_some(())
+    }
+
+    fn consume(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.advance();
+            return true;
+        }
+
+        false
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+        Some(c)
+    }
+}
+
+fn push_literal(nodes: &mut Vec<Node>, literal: &mut String) {
+    if !literal.is_empty() {
+        nodes.push(Node::Literal(std::mem::take(literal)));
+    }
+}
+
+fn adapt_nodes(nodes: &mut [Node], language_code: &str) {
+    for node in nodes {
+        let Node::Branching { kind, branches, .. } = node else {
+            continue;
+        };
+
+        for branch in branches.iter_mut() {
+            adapt_nodes(&mut branch.message, language_code);
+        }
+
+        let categories = match kind {
+            BranchKind::Plural => cardinal_categories(language_code),
+            BranchKind::SelectOrdinal => ordinal_categories(language_code),
+            BranchKind::Select => continue,
+        };
+
+        let Some(other_index) = branches.iter().position(|b| b.selector == "other") else {
+            continue;
+        };
+
+        let other_branch = branches.remove(other_index);
+        branches.retain(|b| {
+            !PLURAL_CATEGORIES.contains(&b.selector.as_str())
+                || categories.contains(&b.selector.as_str())
+        });
+
+        for category in categories.iter().filter(|c| **c != "other") {
+            if !branches.iter().any(|b| b.selector == *category) {
+                branches.push(Branch {
+                    selector: (*category).to_owned(),
+                    message: clone_nodes(&other_branch.message),
+                });
+            }
+        }
+
+        // Exact-value selectors come first, followed by the categories in CLDR order.
+        branches.sort_by_key(|b| {
+            PLURAL_CATEGORIES
+                .iter()
+                .position(|c| *c == b.selector)
+                .map_or(0, |i| i + 1)
+        });
+        branches.push(other_branch);
+    }
+}
+
+fn clone_nodes(nodes: &[Node]) -> Vec<Node> {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Literal(text) => Node::Literal(text.clone()),
+            Node::Argument(text) => Node::Argument(text.clone()),
+            Node::Pound => Node::Pound,
+            Node::Branching {
+                name,
+                kind,
+                offset,
+                branches,
+            } => Node::Branching {
+                name: name.clone(),
+                kind: *kind,
+                offset: offset.clone(),
+                branches: branches
+                    .iter()
+                    .map(|b| Branch {
+                        selector: b.selector.clone(),
+                        message: clone_nodes(&b.message),
+                    })
+                    .collect(),
+            },
+        })
+        .collect()
+}
+
+/// Split a sequence of nodes into runs of literals and simple arguments, separated by branching
+/// arguments.
+fn runs(nodes: &[Node]) -> Vec<&[Node]> {
+    nodes
+        .split(|n| matches!(n, Node::Branching { .. }))
+        .collect()
+}
+
+/// Check whether a run of nodes has any text worth translating.
+fn is_translatable(run: &[Node]) -> bool {
+    run.iter()
+        .any(|n| matches!(n, Node::Literal(text) if text.chars().any(char::is_alphabetic)))
+}
+
+fn collect_fragments(nodes: &[Node], fragments: &mut Vec<Vec<TextPart>>) {
+    for run in runs(nodes) {
+        if is_translatable(run) {
+            fragments.push(
+                run.iter()
+                    .map(|node| match node {
+                        Node::Literal(text) => TextPart::Literal(text.clone()),
+                        Node::Argument(text) => TextPart::Placeholder(text.clone()),
+                        Node::Pound => TextPart::Placeholder("#".to_owned()),
+                        Node::Branching { .. } => unreachable!(),
+                    })
+                    .collect(),
+            );
+        }
+    }
+
+    for node in nodes {
+        if let Node::Branching { branches, .. } = node {
+            for branch in branches {
+                collect_fragments(&branch.message, fragments);
+            }
+        }
+    }
+}
+
+fn render_nodes(
+    nodes: &[Node],
+    in_plural: bool,
+    translated_fragments: &mut impl Iterator<Item = Vec<TextPart>>,
+    rendered: &mut String,
+) {
+    // Fragments are collected run by run before descending into branches, so the translations for
+    // this level must be taken up front to keep them in the same order.
+    let runs = runs(nodes);
+    let mut run_translations = runs
+        .iter()
+        .map(|run| {
+            is_translatable(run)
+                .then(|| translated_fragments.next())
+                .flatten()
+        })
+        .collect::<Vec<_>>()
+        .into_iter();
+
+    let mut branching_nodes = nodes.iter().filter(|n| matches!(n, Node::Branching { .. }));
+
+    for (i, run) in runs.iter().enumerate() {
+        if i > 0
+            && let Some(Node::Branching {
+                name,
+                kind,
+                offset,
+                branches,
+            }) = branching_nodes.next()
+        {
+            rendered.push_str(&format!("{{{name}, {}, ", kind.identifier()));
+            if let Some(offset) = offset {
+                rendered.push_str(&format!("offset:{offset} "));
+            }
+
+            for (j, branch) in branches.iter().enumerate() {
+                if j > 0 {
+                    rendered.push(' ');
+                }
+
+                rendered.push_str(&format!("{} {{", branch.selector));
+                render_nodes(
+                    &branch.message,
+                    in_plural || kind.uses_pound(),
+                    translated_fragments,
+                    rendered,
+                );
+                rendered.push('}');
+            }
+
+            rendered.push('}');
+        }
+
+        match run_translations.next().flatten() {
+            Some(parts) => {
+                for part in parts {
+                    match part {
+                        TextPart::Literal(text) => {
+                            rendered.push_str(&escape_literal(&text, in_plural));
+                        }
+                        TextPart::Placeholder(text) => rendered.push_str(&text),
+                    }
+                }
+            }
+            None => {
+                for node in run.iter() {
+                    match node {
+                        Node::Literal(text) => {
+                            rendered.push_str(&escape_literal(text, in_plural));
+                        }
+                        Node::Argument(text) => rendered.push_str(text),
+                        Node::Pound => rendered.push('#'),
+                        Node::Branching { .. } => unreachable!(),
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Get the text of a message without any ICU escaping, or [`None`] if it contains any branching
+/// arguments.
+fn plain_text(nodes: &[Node]) -> Option<String> {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Literal(text) | Node::Argument(text) => Some(text.as_str()),
+            Node::Pound => Some("#"),
+            Node::Branching { .. } => None,
+        })
+        .collect()
+}
+
+/// Escape literal text so that it is not interpreted as ICU syntax.
+///
+/// Consecutive syntax characters share a single quoted section, since quoting them one by one
+/// would put `''` between the sections, which is a literal apostrophe inside a quoted section.
+/// Apostrophes are written as `''`, which means the same inside and outside quoted sections.
+fn escape_literal(text: &str, in_plural: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut quoted = false;
+    for c in text.chars() {
+        let is_syntax = matches!(c, '{' | '}') || (c == '#' && in_plural);
+        if is_syntax != quoted && c != '\'' {
+            escaped.push('\'');
+            quoted = is_syntax;
+        }
+
+        match c {
+            '\'' => escaped.push_str("''"),
+            _ => escaped.push(c),
+        }
+    }
+
+    if quoted {
+        escaped.push('\'');
+    }
+
+    escaped
+}
+
+/// Get the primary language subtag of a language code, e.g. `pt` for `PT-BR`.
+fn base_language(language_code: &str) -> String {
+    language_code
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Get the CLDR cardinal plural categories used by a language.
+fn cardinal_categories(language_code: &str) -> &'static [&'static str] {
+    match base_language(language_code).as_str() {
+        "ja" | "ko" | "zh" | "th" | "vi" | "id" | "ms" | "lo" | "my" | "km" => &["other"],
+        "fr" | "es" | "it" | "pt" | "ca" => &["one", "many", "other"],
+        "pl" | "ru" | "uk" | "be" | "cs" | "sk" | "lt" => &["one", "few", "many", "other"],
+        "lv" => &["zero", "one", "other"],
+        "ro" | "hr" | "sr" | "bs" => &["one", "few", "other"],
+        "sl" => &["one", "two", "few", "other"],
+        "he" => &["one", "two", "other"],
+        "ga" => &["one", "two", "few", "many", "other"],
+        "ar" | "cy" => &["zero", "one", "two", "few", "many", "other"],
+        _ => &["one", "other"],
+    }
+}
+
+/// Get the CLDR ordinal plural categories used by a language.
+fn ordinal_categories(language_code: &str) -> &'static [&'static str] {
+    match base_language(language_code).as_str() {
+        "en" => &["one", "two", "few", "other"],
+        "fr" | "hu" | "ro" | "sv" | "ms" | "vi" => &["one", "other"],
+        "it" => &["many", "other"],
+        "ca" => &["one", "two", "few", "other"],
+        _ => &["other"],
+    }
+}
+
+/// Get the `Plural-Forms` header gettext uses for a language, along with the CLDR cardinal plural
+/// category of each of its plural form indices.
+///
+/// gettext only counts whole numbers, so categories CLDR uses only for fractions are left out.
+pub fn gettext_plural_forms(language_code: &str) -> (&'static str, &'static [&'static str]) {
+    match base_language(language_code).as_str() {
+        "ja" | "ko" | "zh" | "th" | "vi" | "id" | "ms" | "lo" | "my" | "km" => {
+            ("nplurals=1; plural=0;", &["other"])
+        }
+        "fr" => ("nplurals=2; plural=(n > 1);", &["one", "other"]),
+        "pt" if language_code.eq_ignore_ascii_case("pt-br") => {
+            ("nplurals=2; plural=(n > 1);", &["one", "other"])
+        }
+        "pl" => (
+            "nplurals=3; plural=(n==1 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);",
+            &["one", "few", "many"],
+        ),
+        "ru" | "uk" | "be" => (
+            "nplurals=3; plural=(n%10==1 && n%100!=11 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);",
+            &["one", "few", "many"],
+        ),
+        "cs" | "sk" => (
+            "nplurals=3; plural=(n==1 ? 0 : n>=2 && n<=4 ? 1 : 2);",
+            &["one", "few", "other"],
+        ),
+        "lt" => (
+            "nplurals=3; plural=(n%10==1 && n%100!=11 ? 0 : n%10>=2 && (n%100<10 || n%100>=20) ? 1 : 2);",
+            &["one", "few", "other"],
+        ),
+        "lv" => (
+            "nplurals=3; plural=(n%10==1 && n%100!=11 ? 0 : n != 0 ? 1 : 2);",
+            &["one", "other", "zero"],
+        ),
+        "ro" => (
+            "nplurals=3; plural=(n==1 ? 0 : (n==0 || (n%100 > 0 && n%100 < 20)) ? 1 : 2);",
+            &["one", "few", "other"],
+        ),
+        "hr" | "sr" | "bs" => (
+            "nplurals=3; plural=(n%10==1 && n%100!=11 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2);",
+            &["one", "few", "other"],
+        ),
+        "sl" => (
+            "nplurals=4; plural=(n%100==1 ? 0 : n%100==2 ? 1 : n%100==3 || n%100==4 ? 2 : 3);",
+            &["one", "two", "few", "other"],
+        ),
+        "he" => (
+            "nplurals=3; plural=(n==1 ? 0 : n==2 ? 1 : 2);",
+            &["one", "two", "other"],
+        ),
+        "ga" => (
+            "nplurals=5; plural=(n==1 ? 0 : n==2 ? 1 : n<7 ? 2 : n<11 ? 3 : 4);",
+            &["one", "two", "few", "many", "other"],
+        ),
+        "ar" => (
+            "nplurals=6; plural=(n==0 ? 0 : n==1 ? 1 : n==2 ? 2 : n%100>=3 && n%100<=10 ? 3 : n%100>=11 ? 4 : 5);",
+            &["zero", "one", "two", "few", "many", "other"],
+        ),
+        "cy" => (
+            "nplurals=6; plural=(n==0 ? 0 : n==1 ? 1 : n==2 ? 2 : n==3 ? 3 : n==6 ? 4 : 5);",
+            &["zero", "one", "two", "few", "many", "other"],
+        ),
+        _ => ("nplurals=2; plural=(n != 1);", &["one", "other"]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Describe a fragment as text, with placeholders in angle brackets.
+    fn describe(fragment: &[TextPart]) -> String {
+        fragment
+            .iter()
+            .map(|part| match part {
+                TextPart::Literal(text) => text.clone(),
+                TextPart::Placeholder(text) => format!("<{text}>"),
+            })
+            .collect()
+    }
+
+    /// Render a message with every literal in its fragments replaced using `translate`.
+    fn render_translated(message: &IcuMessage, translate: impl Fn(&str) -> String) -> String {
+        let fragments = message
+            .fragments()
+            .into_iter()
+            .map(|fragment| {
+                fragment
+                    .into_iter()
+                    .map(|part| match part {
+                        TextPart::Literal(text) => TextPart::Literal(translate(&text)),
+                        placeholder => placeholder,
+                    })
+                    .collect()
+            })
+            .collect();
+        message.render(fragments)
+    }
+
+    fn adapted(text: &str, language_code: &str) -> String {
+        let mut message = IcuMessage::parse(text).unwrap();
+        message.adapt_plurals(language_code);
+        message.render(message.fragments())
+    }
+
+    #[test]
+    fn ignores_messages_without_branching() {
+        assert!(IcuMessage::parse("Hello, {name}!").is_none());
+        assert!(IcuMessage::parse("No arguments").is_none());
+        assert!(IcuMessage::parse("{count, plural, one {unclosed}").is_none());
+    }
+
+    #[test]
+    fn splits_nested_plural_and_select() {
+        let text = "{gender, select, female {{count, plural, one {She has # cat} other {She has # cats}}} other {{count, plural, one {They have # cat} other {They have # cats}}}}";
+        let message = IcuMessage::parse(text).unwrap();
+        let fragments = message
+            .fragments()
+            .iter()
+            .map(|f| describe(f))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            fragments,
+            [
+                "She has <#> cat",
+                "She has <#> cats",
+                "They have <#> cat",
+                "They have <#> cats",
+            ]
+        );
+        assert_eq!(message.render(message.fragments()), text);
+        assert_eq!(
+            render_translated(&message, str::to_uppercase),
+            "{gender, select, female {{count, plural, one {SHE HAS # CAT} other {SHE HAS # CATS}}} other {{count, plural, one {THEY HAVE # CAT} other {THEY HAVE # CATS}}}}"
+        );
+    }
+
+    #[test]
+    fn keeps_pound_as_placeholder_only_inside_plurals() {
+        let text = "{count, plural, one {# new message in {channel}} other {# new messages in {channel}}} on #general";
+        let message = IcuMessage::parse(text).unwrap();
+        let fragments = message
+            .fragments()
+            .iter()
+            .map(|f| describe(f))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            fragments,
+            [
+                " on #general",
+                "<#> new message in <{channel}>",
+                "<#> new messages in <{channel}>",
+            ]
+        );
+        assert_eq!(message.render(message.fragments()), text);
+
+        // A `#` added by the translation inside a plural branch is literal text, so it is quoted.
+        let message = IcuMessage::parse("{count, plural, other {# items}}").unwrap();
+        assert_eq!(
+            render_translated(&message, |t| t.replace("items", "#1 items")),
+            "{count, plural, other {# '#'1 items}}"
+        );
+    }
+
+    #[test]
+    fn unescapes_and_escapes_apostrophes() {
+        let text = "{count, plural, one {It''s # file in '{'braces'}'} other {It''s # files}}";
+        let message = IcuMessage::parse(text).unwrap();
+        let fragments = message
+            .fragments()
+            .iter()
+            .map(|f| describe(f))
+            .collect::<Vec<_>>();
+
+        assert_eq!(fragments, ["It's <#> file in {braces}", "It's <#> files"]);
+        assert_eq!(message.render(message.fragments()), text);
+
+        // A lone apostrophe which does not start a quoted section is literal.
+        let message = IcuMessage::parse("{count, plural, other {l'article}}").unwrap();
+        assert_eq!(describe(&message.fragments()[0]), "l'article");
+        assert_eq!(
+            render_translated(&message, |t| t.replace("article", "article d'aujourd'hui")),
+            "{count, plural, other {l''article d''aujourd''hui}}"
+        );
+    }
+
+    #[test]
+    fn adapts_plurals_to_languages_with_more_categories() {
+        assert_eq!(
+            adapted(
+                "{count, plural, =0 {No files} one {# file} other {# files}}",
+                "PL"
+            ),
+            "{count, plural, =0 {No files} one {# file} few {# files} many {# files} other {# files}}"
+        );
+    }
+
+    #[test]
+    fn adapts_plurals_to_languages_without_plurals() {
+        assert_eq!(
+            adapted(
+                "{count, plural, =0 {No files} one {# file} other {# files}}",
+                "JA"
+            ),
+            "{count, plural, =0 {No files} other {# files}}"
+        );
+    }
+
+    #[test]
+    fn adapts_nested_plurals_and_ordinals() {
+        assert_eq!(
+            adapted(
+                "{gender, select, female {{count, plural, one {# cat} other {# cats}}} other {{n, selectordinal, one {#st} two {#nd} few {#rd} other {#th}}}}",
+                "FR"
+            ),
+            "{gender, select, female {{count, plural, one {# cat} many {# cats} other {# cats}}} other {{n, selectordinal, one {#st} other {#th}}}}"
+        );
+    }
+
+    #[test]
+    fn converts_gettext_plural_forms() {
+        let text = IcuMessage::plural_text(&[("one", "# file"), ("other", "{# files}")]);
+        assert_eq!(
+            text,
+            "{count, plural, one {'#' file} other {'{#' files'}'}}"
+        );
+        assert_eq!(
+            IcuMessage::plural_forms(&text).unwrap(),
+            [
+                ("one".to_owned(), "# file".to_owned()),
+                ("other".to_owned(), "{# files}".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes_literals_next_to_apostrophes() {
+        for literal in ["'{x}'", "it's {#}", "''#''", "{'}", "a '' b"] {
+            let text = IcuMessage::plural_text(&[("other", literal)]);
+            assert_eq!(
+                IcuMessage::plural_forms(&text).unwrap(),
+                [("other".to_owned(), literal.to_owned())],
+                "{text}"
+            );
+        }
+    }
+}
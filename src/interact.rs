@@ -1,34 +1,59 @@
 use std::collections::BTreeMap;
-use std::path::PathBuf;
+use std::fmt::{Display, Formatter};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 
-use color_print::cformat;
+use color_print::{ceprintln, cformat};
 use dialoguer::theme::ColorfulTheme;
 use dialoguer::{Confirm, FuzzySelect, Input, MultiSelect, Select};
 
+use crate::backend::{Formality, TranslationBackend};
 use crate::helper_functions::file_exists;
-use crate::types::{DeepLContext, Language};
+use crate::locale_file::LocaleFormat;
+use crate::types::Language;
 use crate::{LANG_DIR_PATH, exit};
 
 pub enum ProjectSetting {
-    EditSourcePath,
-    EditLangugages,
+    SourcePath,
+    SourceLanguage,
+    Languages,
+    Formality,
 }
 
-impl ToString for ProjectSetting {
-    fn to_string(&self) -> String {
+impl Display for ProjectSetting {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            ProjectSetting::EditSourcePath => "source locale path".to_owned(),
-            ProjectSetting::EditLangugages => "enabled languages".to_owned(),
+            ProjectSetting::SourcePath => write!(f, "source locale path"),
+            ProjectSetting::SourceLanguage => write!(f, "source language"),
+            ProjectSetting::Languages => write!(f, "enabled languages"),
+            ProjectSetting::Formality => write!(f, "formality"),
         }
     }
 }
 
+/// Exit with an error if stdin is not a terminal, as prompts cannot be answered in that case.
+///
+/// `flag_hint` should name the command-line flag(s) which can be used to provide the value instead.
+pub fn require_terminal(flag_hint: &str) {
+    if !std::io::stdin().is_terminal() {
+        exit!(
+            "Cannot prompt for input because stdin is not a terminal. Use <m>{}</> to provide this value instead.",
+            flag_hint
+        );
+    }
+}
+
 pub fn select_project_setting() -> ProjectSetting {
+    require_terminal(
+        "--set-source, --set-source-lang, --add-lang, --remove-lang, or --set-formality",
+    );
     let Ok(setting_index) = Select::with_theme(&ColorfulTheme::default())
         .with_prompt("What setting would you like to change?")
         .items([
-            ProjectSetting::EditSourcePath,
-            ProjectSetting::EditLangugages,
+            ProjectSetting::SourcePath,
+            ProjectSetting::SourceLanguage,
+            ProjectSetting::Languages,
+            ProjectSetting::Formality,
         ])
         .interact()
     else {
@@ -36,31 +61,38 @@ pub fn select_project_setting() -> ProjectSetting {
     };
 
     match setting_index {
-        0 => ProjectSetting::EditSourcePath,
-        1 => ProjectSetting::EditLangugages,
+        0 => ProjectSetting::SourcePath,
+        1 => ProjectSetting::SourceLanguage,
+        2 => ProjectSetting::Languages,
+        3 => ProjectSetting::Formality,
         _ => exit!("Unknown error occurred with the settings selector."),
     }
 }
 
-pub fn select_target_language(deepl_context: &DeepLContext) -> Language {
+pub fn select_target_language(backend: &dyn TranslationBackend) -> Language {
+    require_terminal("--language");
     let Ok(lang_index) = FuzzySelect::with_theme(&ColorfulTheme::default())
         .with_prompt("What language do you want to translate to?")
-        .items(&deepl_context.available_target_langs)
+        .items(backend.target_languages())
         .interact()
     else {
         exit!("Unknown error occurred with language selector.")
     };
 
-    deepl_context.available_target_langs[lang_index].clone()
+    backend.target_languages()[lang_index].clone()
 }
 
 pub fn select_target_languages(
-    deepl_context: &DeepLContext,
+    backend: &dyn TranslationBackend,
     enabled_languages: Option<&[Language]>,
 ) -> Vec<Language> {
+    require_terminal(match enabled_languages {
+        Some(_) => "--add-lang or --remove-lang",
+        None => "--lang",
+    });
     let preselected_langs = match enabled_languages {
-        Some(enabled_langs) => deepl_context
-            .available_target_langs
+        Some(enabled_langs) => backend
+            .target_languages()
             .iter()
             .map(|l| enabled_langs.contains(l))
             .collect(),
@@ -69,67 +101,156 @@ pub fn select_target_languages(
 
     let Ok(selected_lang_indices) = MultiSelect::with_theme(&ColorfulTheme::default())
         .with_prompt("What languages do you want to translate to?")
-        .items(&deepl_context.available_target_langs)
+        .items(backend.target_languages())
         .defaults(&preselected_langs)
         .interact()
     else {
         exit!("Unknown error occurred with language selector.");
     };
 
-    deepl_context
-        .available_target_langs
+    backend
+        .target_languages()
         .iter()
         .enumerate()
         .filter_map(|(i, l)| selected_lang_indices.contains(&i).then_some(l.clone()))
         .collect()
 }
 
-pub fn select_source_locale() -> PathBuf {
+/// Prompt the user to pick one of the enabled languages of a project.
+pub fn select_enabled_language(languages: &[Language]) -> Language {
+    require_terminal("--set-formality");
+    let Ok(lang_index) = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Which language do you want to change?")
+        .items(languages)
+        .interact()
+    else {
+        exit!("Unknown error occurred with language selector.")
+    };
+
+    languages[lang_index].clone()
+}
+
+/// Prompt the user to pick a formality for a language, starting at its current formality.
+pub fn select_formality(language: &Language, current: Formality) -> Formality {
+    require_terminal("--set-formality");
+    let Ok(formality_index) = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!(
+            "How formal should translations into {} be?",
+            language.name
+        ))
+        .items(Formality::IDENTIFIERS)
+        .default(
+            Formality::IDENTIFIERS
+                .iter()
+                .position(|i| *i == current.identifier())
+                .unwrap_or_default(),
+        )
+        .interact()
+    else {
+        exit!("Unknown error occurred with the formality selector.");
+    };
+
+    Formality::from_identifier(Formality::IDENTIFIERS[formality_index]).unwrap_or_default()
+}
+
+/// Find a target language by its code, opening the language selector if the code is invalid.
+pub fn resolve_target_language(backend: &dyn TranslationBackend, code: &str) -> Language {
+    match find_language(backend.target_languages(), code) {
+        Some(language) => language,
+        None => {
+            ceprintln!("Unknown target language <r>'{}'</>.", code);
+            select_target_language(backend)
+        }
+    }
+}
+
+/// Find a source language by its code, opening the language selector if the code is invalid.
+pub fn resolve_source_language(backend: &dyn TranslationBackend, code: &str) -> Language {
+    match find_language(backend.source_languages(), code) {
+        Some(language) => language,
+        None => {
+            ceprintln!("Unknown source language <r>'{}'</>.", code);
+            select_source_language(backend)
+        }
+    }
+}
+
+/// Find a language in a list by its code, ignoring case.
+pub fn find_language(languages: &[Language], code: &str) -> Option<Language> {
+    languages
+        .iter()
+        .find(|l| l.code.eq_ignore_ascii_case(code))
+        .cloned()
+}
+
+pub fn select_source_language(backend: &dyn TranslationBackend) -> Language {
+    require_terminal("--source-lang");
+    let source_languages = backend.source_languages();
+    let default_index = source_languages
+        .iter()
+        .position(|l| l.code == Language::english().code)
+        .unwrap_or_default();
+
+    let Ok(lang_index) = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("What language is your source locale file written in?")
+        .items(source_languages)
+        .default(default_index)
+        .interact()
+    else {
+        exit!("Unknown error occurred with language selector.")
+    };
+
+    source_languages[lang_index].clone()
+}
+
+pub fn select_source_locale(source_language: &Language) -> PathBuf {
+    require_terminal("--source");
     loop {
-        let english_locale_path: PathBuf = input_prompt(
-            "What is the name of the English locale file?",
-            Some(format!("{LANG_DIR_PATH}/en.json")),
+        let source_locale_path: PathBuf = input_prompt(
+            &format!(
+                "What is the name of the {} locale file?",
+                source_language.name
+            ),
+            Some(format!(
+                "{LANG_DIR_PATH}/{}.json",
+                source_language.code.to_lowercase()
+            )),
         )
         .into();
-        if !file_exists(&english_locale_path) {
+        if !file_exists(&source_locale_path) {
             eprintln!("The file you specified does not exist. Please try again.");
             continue;
         }
 
-        return english_locale_path;
+        return source_locale_path;
     }
 }
 
-pub fn select_output_locale_all(target_languages: &[Language]) -> BTreeMap<String, PathBuf> {
+pub fn select_output_locale_all(
+    target_languages: &[Language],
+    format: LocaleFormat,
+) -> BTreeMap<String, PathBuf> {
     target_languages
         .iter()
-        .map(|l| (l.code.clone(), select_output_locale(l)))
+        .map(|l| (l.code.clone(), select_output_locale(l, format)))
         .collect()
 }
 
-pub fn select_output_locale(target_language: &Language) -> PathBuf {
-    let default_path = format!(
-        "{LANG_DIR_PATH}/{}.json",
-        target_language.code.to_lowercase()
-    );
+pub fn select_output_locale(target_language: &Language, format: LocaleFormat) -> PathBuf {
+    require_terminal("CODE=PATH with --lang or --add-lang");
+    let default_path = default_output_locale(target_language, format);
 
     loop {
-        let output_locale_path = input_prompt(
+        let output_locale_path = PathBuf::from(input_prompt(
             &cformat!(
                 "<c>[{}]</> What should the output file be called?",
                 target_language.to_string(),
             ),
-            Some(default_path.clone()),
-        );
+            Some(default_path.to_string_lossy().into_owned()),
+        ));
 
-        if !output_locale_path.ends_with(".json") {
-            eprintln!("The file must have a .json extension.");
-            continue;
-        }
-
-        let output_locale_path = PathBuf::from(output_locale_path);
-        if output_locale_path.exists() {
-            eprintln!("The file you specified already exists. Please give it a different name.");
+        if let Err(e) = validate_output_locale(&output_locale_path, format) {
+            eprintln!("{e} Please try again.");
             continue;
         }
 
@@ -137,7 +258,60 @@ pub fn select_output_locale(target_language: &Language) -> PathBuf {
     }
 }
 
+/// Get the path a locale file is given if the user does not choose one, which uses the same
+/// format as the source locale.
+pub fn default_output_locale(target_language: &Language, format: LocaleFormat) -> PathBuf {
+    PathBuf::from(format!(
+        "{LANG_DIR_PATH}/{}.{}",
+        target_language.code.to_lowercase(),
+        format.extension()
+    ))
+}
+
+/// Check whether a path can be used for a new locale file, translated from a source locale in
+/// `source_format`.
+pub fn validate_output_locale(
+    path: &Path,
+    source_format: LocaleFormat,
+) -> Result<(), &'static str> {
+    if LocaleFormat::from_path(path).is_none() {
+        return Err("The file must have a .json, .yaml, .yml, or .po extension.");
+    }
+
+    if path.extension().is_some_and(|e| e == "pot") {
+        return Err("POT templates can only be used as the source locale; use a .po file instead.");
+    }
+
+    validate_output_format(path, source_format)?;
+
+    if path.exists() {
+        return Err("The file you specified already exists.");
+    }
+
+    Ok(())
+}
+
+/// Check whether a locale file can be written in the format of `path` when it is translated from a
+/// source locale in `source_format`.
+///
+/// A PO file can only hold entries identified by a msgid and an optional msgctxt, so other formats
+/// cannot be translated into one without losing values.
+pub fn validate_output_format(
+    path: &Path,
+    source_format: LocaleFormat,
+) -> Result<(), &'static str> {
+    if LocaleFormat::from_path(path) == Some(LocaleFormat::Po) && source_format != LocaleFormat::Po
+    {
+        return Err(
+            "PO files can only be translated from a source locale which is a PO or POT file.",
+        );
+    }
+
+    Ok(())
+}
+
 pub fn confirm_prompt(prompt_text: &str) -> bool {
+    require_terminal("--yes");
     let Ok(response) = Confirm::new()
         .with_prompt(prompt_text)
         .default(true)
@@ -0,0 +1,134 @@
+use serde_json::Value as JsonValue;
+
+use crate::types::{FlatLocaleData, LocaleData};
+
+/// The separator placed between segments of a key path, e.g. `auth.login.title`.
+const SEPARATOR: char = '.';
+/// The character used to escape literal separators in object keys, e.g. `"file\.name"`.
+const ESCAPE: char = '\\';
+
+/// Flatten nested [`LocaleData`] into a map from key paths to string values, in document order.
+///
+/// Objects contribute their keys as path segments and arrays contribute their indices. Only string
+/// leaves are collected, as they are the only values which can be translated; numbers, booleans,
+/// and nulls are carried over from the source structure by [`rebuild()`]. Metadata keys are left
+/// out entirely (see [`is_metadata_key()`]).
+pub fn flatten(data: &LocaleData) -> FlatLocaleData {
+    let mut flat_data = FlatLocaleData::new();
+    for (key, value) in data.iter().filter(|(k, _)| !is_metadata_key(k, data)) {
+        flatten_value(&escape_segment(key), value, &mut flat_data);
+    }
+
+    flat_data
+}
+
+/// Rebuild nested [`LocaleData`] using the structure of `skeleton` and the string values in
+/// `flat_data`.
+///
+/// Any string leaf in the skeleton which has no corresponding key path in `flat_data` is omitted
+/// from the output, as are objects and arrays which end up empty because of this. Metadata keys
+/// are always omitted, as they only describe the source locale.
+pub fn rebuild(skeleton: &LocaleData, flat_data: &FlatLocaleData) -> LocaleData {
+    skeleton
+        .iter()
+        .filter(|(k, _)| !is_metadata_key(k, skeleton))
+        .filter_map(|(key, value)| {
+            rebuild_value(&escape_segment(key), value, flat_data).map(|v| (key.clone(), v))
+        })
+        .collect()
+}
+
+/// Join a parent key path and an unescaped child segment.
+pub fn join(parent: &str, segment: &str) -> String {
+    format!("{parent}{SEPARATOR}{}", escape_segment(segment))
+}
+
+/// Check whether a key of `object` holds metadata instead of a value, as in ARB files, where
+/// `"@title"` describes `"title"`.
+///
+/// A key starting with `@` is only metadata if the key it describes exists, so that values such
+/// as `"@mention": "Mention someone"` are still translated. Keys starting with `@@`, such as
+/// `"@@locale"`, describe the whole file and are always metadata.
+pub fn is_metadata_key(key: &str, object: &LocaleData) -> bool {
+    key.strip_prefix('@')
+        .is_some_and(|described| described.starts_with('@') || object.contains_key(described))
+}
+
+/// Get the last segment of a key path, still escaped, e.g. `title` for `auth.login.title`.
+pub fn last_segment(path: &str) -> &str {
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in path.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            ESCAPE => escaped = true,
+            SEPARATOR => start = i + c.len_utf8(),
+            _ => {}
+        }
+    }
+
+    &path[start..]
+}
+
+fn flatten_value(path: &str, value: &JsonValue, flat_data: &mut FlatLocaleData) {
+    match value {
+        JsonValue::String(_) => {
+            flat_data.insert(path.to_owned(), value.clone());
+        }
+        JsonValue::Object(object) => {
+            for (key, child) in object.iter().filter(|(k, _)| !is_metadata_key(k, object)) {
+                flatten_value(&join(path, key), child, flat_data);
+            }
+        }
+        JsonValue::Array(array) => {
+            for (index, child) in array.iter().enumerate() {
+                flatten_value(&join(path, &index.to_string()), child, flat_data);
+            }
+        }
+        JsonValue::Null | JsonValue::Bool(_) | JsonValue::Number(_) => {}
+    }
+}
+
+fn rebuild_value(path: &str, value: &JsonValue, flat_data: &FlatLocaleData) -> Option<JsonValue> {
+    match value {
+        JsonValue::String(_) => flat_data.get(path).cloned(),
+        JsonValue::Object(object) => {
+            let rebuilt = object
+                .iter()
+                .filter(|(k, _)| !is_metadata_key(k, object))
+                .filter_map(|(key, child)| {
+                    rebuild_value(&join(path, key), child, flat_data).map(|v| (key.clone(), v))
+                })
+                .collect::<LocaleData>();
+
+            (object.is_empty() || !rebuilt.is_empty()).then_some(JsonValue::Object(rebuilt))
+        }
+        JsonValue::Array(array) => {
+            let rebuilt = array
+                .iter()
+                .enumerate()
+                .filter_map(|(index, child)| {
+                    rebuild_value(&join(path, &index.to_string()), child, flat_data)
+                })
+                .collect::<Vec<_>>();
+
+            (array.is_empty() || !rebuilt.is_empty()).then_some(JsonValue::Array(rebuilt))
+        }
+        JsonValue::Null | JsonValue::Bool(_) | JsonValue::Number(_) => Some(value.clone()),
+    }
+}
+
+/// Escape any separators or escape characters in an object key so it can be used as a single
+/// segment of a key path.
+pub fn escape_segment(segment: &str) -> String {
+    let mut escaped = String::with_capacity(segment.len());
+    for c in segment.chars() {
+        if c == SEPARATOR || c == ESCAPE {
+            escaped.push(ESCAPE);
+        }
+
+        escaped.push(c);
+    }
+
+    escaped
+}
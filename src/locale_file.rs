@@ -0,0 +1,667 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde_json::{Map as JsonMap, Number as JsonNumber, Value as JsonValue};
+use serde_yaml::Value as YamlValue;
+
+use crate::key_path;
+use crate::types::{FlatLocaleData, LocaleData};
+
+mod po;
+
+pub use po::GettextConfig;
+
+/// The file formats locale files can be written in, chosen by their file extension.
+#[derive(Clone, Copy, PartialEq)]
+pub enum LocaleFormat {
+    Json,
+    /// YAML, as used by Rails and other i18n libraries, optionally with the whole locale nested
+    /// under a top-level language key such as `en:`.
+    Yaml,
+    /// GNU gettext PO files, or POT templates, which can only be used as the source locale.
+    Po,
+}
+
+/// The parts of a locale file which are not part of its data, kept so that the file can be written
+/// back the way it was laid out.
+#[derive(Clone, Default)]
+pub struct LocaleLayout {
+    /// The top-level language key the data is nested under, as in Rails locale files.
+    root: Option<String>,
+    /// Comments on their own line, keyed by the key path of the entry they come before. Comments
+    /// before the top-level language key are keyed by an empty path.
+    comments: BTreeMap<String, Vec<String>>,
+    /// Comments after the last entry of the file.
+    trailing_comments: Vec<String>,
+    po: po::PoLayout,
+}
+
+/// Words which YAML 1.1 parsers, such as the one Rails uses, read as booleans unless they are
+/// quoted. Newer parsers read them as strings, so they are not quoted when serializing.
+const YAML_1_1_BOOLEANS: [&str; 16] = [
+    "y", "Y", "yes", "Yes", "YES", "n", "N", "no", "No", "NO", "on", "On", "ON", "off", "Off",
+    "OFF",
+];
+
+/// What a single line of a YAML file contains, as far as comments are concerned.
+enum YamlLine {
+    /// The start of a mapping entry, along with the keys leading to it, or [`None`] if it is inside
+    /// a sequence.
+    Entry(Option<Vec<String>>),
+    /// The start of a sequence item.
+    Item,
+    Comment,
+    Other,
+}
+
+impl LocaleFormat {
+    /// Get the format of a locale file from its extension, if it is supported.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(LocaleFormat::Json),
+            "yaml" | "yml" => Some(LocaleFormat::Yaml),
+            "po" | "pot" => Some(LocaleFormat::Po),
+            _ => None,
+        }
+    }
+
+    /// Get the format a locale file is read and written in. Files without a supported extension
+    /// are treated as JSON.
+    pub fn of(path: &Path) -> Self {
+        LocaleFormat::from_path(path).unwrap_or(LocaleFormat::Json)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            LocaleFormat::Json => "json",
+            LocaleFormat::Yaml => "yml",
+            LocaleFormat::Po => "po",
+        }
+    }
+}
+
+impl LocaleLayout {
+    /// Get the layout of a new locale file for a different language, which keeps the comments of
+    /// this one, and uses the new language as its top-level key if this one has one.
+    pub fn for_language(&self, language_code: &str) -> Self {
+        LocaleLayout {
+            root: self.root.as_ref().map(|_| language_tag(language_code, '-')),
+            po: self.po.for_language(),
+            ..self.clone()
+        }
+    }
+
+    /// Bring the layout of a foreign locale file up to date with that of the source locale.
+    ///
+    /// The comments of YAML files belong to each file, so only PO files are affected; see
+    /// [`po::PoLayout::update_from_source()`].
+    pub fn update_from_source(&mut self, source_layout: &LocaleLayout) {
+        self.po.update_from_source(&source_layout.po);
+    }
+
+    /// Record which entries were just translated, flagging them as `fuzzy` in PO files if
+    /// [`GettextConfig::mark_fuzzy`] is set.
+    pub fn mark_translated(&mut self, translated_data: &FlatLocaleData, config: &GettextConfig) {
+        self.po
+            .mark_translated(translated_data.keys(), config.mark_fuzzy);
+    }
+}
+
+/// Parse the contents of a locale file of a given language.
+///
+/// A YAML file whose only top-level key is the language code (such as `en:` or `pt-BR:`) has its
+/// data taken from below that key. How PO files are read depends on whether they are the source
+/// locale; see [`po::parse()`].
+pub fn parse(
+    text: &str,
+    format: LocaleFormat,
+    language_code: &str,
+    is_source: bool,
+) -> Result<(LocaleData, LocaleLayout), String> {
+    let mut data = match format {
+        LocaleFormat::Json => {
+            let data = serde_json::from_str::<LocaleData>(text).map_err(|e| e.to_string())?;
+            return Ok((data, LocaleLayout::default()));
+        }
+        LocaleFormat::Po => {
+            let (data, po) = po::parse(text, language_code, is_source)?;
+            let layout = LocaleLayout {
+                po,
+                ..LocaleLayout::default()
+            };
+            return Ok((data, layout));
+        }
+        LocaleFormat::Yaml => {
+            let value = serde_yaml::from_str::<YamlValue>(text).map_err(|e| e.to_string())?;
+            match yaml_to_json(value)? {
+                JsonValue::Object(data) => data,
+                _ => return Err("the file must contain a single top-level mapping".to_owned()),
+            }
+        }
+    };
+
+    let root = match data.iter().next() {
+        Some((key, JsonValue::Object(_))) if data.len() == 1 && is_root_key(key, language_code) => {
+            Some(key.clone())
+        }
+        _ => None,
+    };
+
+    let (trailing_comments, comments) = read_comments(text, root.is_some());
+    let layout = LocaleLayout {
+        comments,
+        trailing_comments,
+        root,
+        ..LocaleLayout::default()
+    };
+
+    if let Some(root) = &layout.root
+        && let Some(JsonValue::Object(nested_data)) = data.remove(root)
+    {
+        return Ok((nested_data, layout));
+    }
+
+    Ok((data, layout))
+}
+
+/// Format locale data as the contents of a locale file of a given language.
+pub fn render(
+    data: &LocaleData,
+    format: LocaleFormat,
+    layout: &LocaleLayout,
+    language_code: &str,
+) -> Result<String, String> {
+    match format {
+        LocaleFormat::Json => return serde_json::to_string_pretty(data).map_err(|e| e.to_string()),
+        LocaleFormat::Po => return po::render(data, &layout.po, language_code),
+        LocaleFormat::Yaml => (),
+    }
+
+    let value = match &layout.root {
+        Some(root) => {
+            let mut rooted = LocaleData::new();
+            rooted.insert(root.clone(), JsonValue::Object(data.clone()));
+            serde_yaml::to_value(rooted)
+        }
+        None => serde_yaml::to_value(data),
+    };
+    let text = value
+        .and_then(|v| serde_yaml::to_string(&v))
+        .map_err(|e| e.to_string())?;
+
+    Ok(finish_yaml(&text, layout))
+}
+
+/// Bring translated values into the form they take once they are written to a locale file of a
+/// given language and read back, so that they are not mistaken for values edited by hand.
+pub fn normalize(
+    translated_data: FlatLocaleData,
+    format: LocaleFormat,
+    language_code: &str,
+) -> FlatLocaleData {
+    match format {
+        LocaleFormat::Po => po::normalize(translated_data, language_code),
+        LocaleFormat::Json | LocaleFormat::Yaml => translated_data,
+    }
+}
+
+/// Get the tag of a language in the form used by Rails and gettext, such as `de` or `pt-BR`, with
+/// a given separator between its subtags.
+fn language_tag(language_code: &str, separator: char) -> String {
+    let mut segments = language_code.split('-');
+    let language = segments.next().unwrap_or(language_code).to_lowercase();
+
+    segments
+        .filter(|s| !s.is_empty())
+        .fold(language, |tag, segment| {
+            let segment = match (segment.len(), segment.split_at_checked(1)) {
+                (2, _) => segment.to_uppercase(),
+                (_, Some((first, rest))) => first.to_uppercase() + &rest.to_lowercase(),
+                (_, None) => segment.to_lowercase(),
+            };
+            format!("{tag}{separator}{segment}")
+        })
+}
+
+/// Check whether a top-level key names the language of its locale file, ignoring region and case,
+/// so that `en`, `en-US`, and `en_GB` all match `EN`.
+fn is_root_key(key: &str, language_code: &str) -> bool {
+    let base_language = |code: &str| code.split(['-', '_']).next().unwrap_or(code).to_lowercase();
+    base_language(key) == base_language(language_code)
+}
+
+/// Convert a YAML value to JSON, resolving `<<` merge keys in place so that the order of the keys
+/// is kept.
+fn yaml_to_json(value: YamlValue) -> Result<JsonValue, String> {
+    Ok(match value {
+        YamlValue::Null => JsonValue::Null,
+        YamlValue::Bool(b) => JsonValue::Bool(b),
+        YamlValue::Number(n) => match (n.as_i64(), n.as_u64()) {
+            (Some(i), _) => JsonValue::from(i),
+            (_, Some(u)) => JsonValue::from(u),
+            _ => n
+                .as_f64()
+                .and_then(JsonNumber::from_f64)
+                .map(JsonValue::Number)
+                .ok_or_else(|| format!("unsupported number {n}"))?,
+        },
+        YamlValue::String(s) => JsonValue::String(s),
+        YamlValue::Sequence(items) => JsonValue::Array(
+            items
+                .into_iter()
+                .map(yaml_to_json)
+                .collect::<Result<_, _>>()?,
+        ),
+        YamlValue::Mapping(mapping) => {
+            let mut object = JsonMap::new();
+            for (key, value) in mapping {
+                if key.as_str() != Some("<<") {
+                    object.insert(yaml_key(key)?, yaml_to_json(value)?);
+                    continue;
+                }
+
+                let merged = match yaml_to_json(value)? {
+                    JsonValue::Array(merged) => merged,
+                    merged => vec![merged],
+                };
+                for merged in merged {
+                    let JsonValue::Object(merged) = merged else {
+                        return Err("merge keys must refer to mappings".to_owned());
+                    };
+
+                    for (key, value) in merged {
+                        object.entry(key).or_insert(value);
+                    }
+                }
+            }
+
+            JsonValue::Object(object)
+        }
+        YamlValue::Tagged(tagged) => yaml_to_json(tagged.value)?,
+    })
+}
+
+/// Convert a mapping key to a string, as keys such as `1:` or `true:` are not strings in YAML.
+fn yaml_key(key: YamlValue) -> Result<String, String> {
+    match key {
+        YamlValue::String(s) => Ok(s),
+        YamlValue::Number(n) => Ok(n.to_string()),
+        YamlValue::Bool(b) => Ok(b.to_string()),
+        _ => Err("mapping keys must be strings".to_owned()),
+    }
+}
+
+/// Collect the comments of a YAML file which are on their own line, keyed by the entry they come
+/// before, along with any comments at the end of the file.
+///
+/// Comments before entries inside sequences cannot be matched up again, so they are left out.
+fn read_comments(text: &str, rooted: bool) -> (Vec<String>, BTreeMap<String, Vec<String>>) {
+    let mut comments = BTreeMap::new();
+    let mut pending = Vec::new();
+    for (line, kind) in text.lines().zip(scan_yaml_lines(text)) {
+        match kind {
+            YamlLine::Comment => pending.push(line.trim().to_owned()),
+            YamlLine::Entry(Some(keys)) if !pending.is_empty() => {
+                comments.insert(entry_path(&keys, rooted), std::mem::take(&mut pending));
+            }
+            YamlLine::Entry(_) | YamlLine::Item => pending.clear(),
+            YamlLine::Other if !line.trim().is_empty() => pending.clear(),
+            YamlLine::Other => (),
+        }
+    }
+
+    (pending, comments)
+}
+
+/// Add the comments of a layout back into a serialized YAML file, indented to match the entries
+/// they come before, and quote any keys and values which YAML 1.1 parsers would read as booleans.
+fn finish_yaml(text: &str, layout: &LocaleLayout) -> String {
+    let mut output = String::with_capacity(text.len());
+    for (line, kind) in text.lines().zip(scan_yaml_lines(text)) {
+        if let YamlLine::Entry(Some(keys)) = &kind
+            && let Some(comments) = layout
+                .comments
+                .get(&entry_path(keys, layout.root.is_some()))
+        {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            for comment in comments {
+                output.push_str(&format!("{indent}{comment}\n"));
+            }
+        }
+
+        match kind {
+            YamlLine::Entry(_) | YamlLine::Item => output.push_str(&quote_booleans(line)),
+            _ => output.push_str(line),
+        }
+        output.push('\n');
+    }
+
+    for comment in &layout.trailing_comments {
+        output.push_str(&format!("{comment}\n"));
+    }
+
+    output
+}
+
+/// Get the key path of an entry from the keys leading to it, leaving out the top-level language
+/// key if there is one.
+fn entry_path(keys: &[String], rooted: bool) -> String {
+    let keys = match rooted {
+        true => keys.get(1..).unwrap_or_default(),
+        false => keys,
+    };
+
+    let mut keys = keys.iter();
+    let first = keys.next().map(|k| key_path::escape_segment(k));
+    keys.fold(first.unwrap_or_default(), |path, key| {
+        key_path::join(&path, key)
+    })
+}
+
+/// Work out what each line of a block-style YAML file contains, following the indentation of
+/// mapping entries to find the keys leading to each one.
+///
+/// This is not a full YAML parser; lines it cannot make sense of are treated as
+/// [`YamlLine::Other`], which only means the comments around them are not kept.
+fn scan_yaml_lines(text: &str) -> Vec<YamlLine> {
+    let mut lines = Vec::new();
+    // The indentation and key of every entry the current line may be nested in. Sequences are
+    // entered with no key, and one more than their indentation.
+    let mut open_entries: Vec<(usize, Option<String>)> = Vec::new();
+    // The indentation of the entry whose multi-line string is being read, if any.
+    let mut block_scalar_indent = None;
+
+    for line in text.lines() {
+        let content = line.trim_start();
+        let indent = line.len() - content.len();
+
+        if let Some(block_indent) = block_scalar_indent {
+            if content.is_empty() || indent > block_indent {
+                lines.push(YamlLine::Other);
+                continue;
+            }
+
+            block_scalar_indent = None;
+        }
+
+        if content.starts_with('#') {
+            lines.push(YamlLine::Comment);
+            continue;
+        }
+
+        if content.is_empty() || content.starts_with("---") || content.starts_with("...") {
+            lines.push(YamlLine::Other);
+            continue;
+        }
+
+        if let Some(item) = content.strip_prefix('-')
+            && (item.is_empty() || item.starts_with(' '))
+        {
+            while open_entries.last().is_some_and(|(i, _)| *i > indent) {
+                open_entries.pop();
+            }
+
+            open_entries.push((indent + 1, None));
+            if is_block_scalar(item.trim_start())
+                || split_key(item.trim_start()).is_some_and(|(_, v)| is_block_scalar(v))
+            {
+                block_scalar_indent = Some(indent);
+            }
+
+            lines.push(YamlLine::Item);
+            continue;
+        }
+
+        let Some((key, value)) = split_key(content) else {
+            lines.push(YamlLine::Other);
+            continue;
+        };
+
+        while open_entries.last().is_some_and(|(i, _)| *i >= indent) {
+            open_entries.pop();
+        }
+
+        let keys = open_entries
+            .iter()
+            .map(|(_, k)| k.clone())
+            .chain(std::iter::once(Some(key.clone())))
+            .collect::<Option<Vec<_>>>();
+        open_entries.push((indent, Some(key)));
+
+        if is_block_scalar(value) {
+            block_scalar_indent = Some(indent);
+        }
+
+        lines.push(YamlLine::Entry(keys));
+    }
+
+    lines
+}
+
+/// Split a line which starts a mapping entry into its key and the rest of the line after the
+/// colon, or return [`None`] if the line does not start a mapping entry.
+fn split_key(content: &str) -> Option<(String, &str)> {
+    let (key, rest) = match content.chars().next()? {
+        '"' => {
+            let mut escaped = false;
+            let (end, _) = content.char_indices().skip(1).find(|(_, c)| {
+                let is_end = *c == '"' && !escaped;
+                escaped = *c == '\\' && !escaped;
+                is_end
+            })?;
+            let key = content[1..end].replace("\\\"", "\"").replace("\\\\", "\\");
+            (key, &content[end + 1..])
+        }
+        '\'' => {
+            let mut end = 1;
+            loop {
+                end += content[end..].find('\'')?;
+                if content[end + 1..].starts_with('\'') {
+                    end += 2;
+                    continue;
+                }
+
+                break;
+            }
+
+            (content[1..end].replace("''", "'"), &content[end + 1..])
+        }
+        '{' | '[' | '!' | '&' | '*' | '?' | '|' | '>' | '%' | '@' | '`' => return None,
+        _ => {
+            let end = content
+                .find(": ")
+                .or_else(|| content.strip_suffix(':').map(str::len))?;
+            (content[..end].trim_end().to_owned(), &content[end..])
+        }
+    };
+
+    let rest = rest.trim_start().strip_prefix(':')?;
+    match rest.is_empty() || rest.starts_with([' ', '\t']) {
+        true => Some((key, rest.trim_start())),
+        false => None,
+    }
+}
+
+/// Quote the plain key and value of a serialized mapping entry or sequence item if they are in
+/// [`YAML_1_1_BOOLEANS`].
+fn quote_booleans(line: &str) -> String {
+    let content = line.trim_start();
+    let indent = &line[..line.len() - content.len()];
+    let (item_prefix, content) = match content.strip_prefix("- ") {
+        Some(item) => ("- ", item),
+        None => ("", content),
+    };
+
+    let quote = |text: &str| match YAML_1_1_BOOLEANS.contains(&text) {
+        true => format!("'{text}'"),
+        false => text.to_owned(),
+    };
+
+    let content = match content.split_once(": ") {
+        Some((key, value)) => format!("{}: {}", quote(key), quote(value)),
+        None => match content.strip_suffix(':') {
+            Some(key) => format!("{}:", quote(key)),
+            None => quote(content),
+        },
+    };
+
+    format!("{indent}{item_prefix}{content}")
+}
+
+/// Check whether the value of an entry starts a multi-line string, such as `|` or `>-`.
+fn is_block_scalar(value: &str) -> bool {
+    value.starts_with(['|', '>'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Rails locale file laid out exactly as it is written back.
+    const RAILS_FILE: &str = r#"# Translations for the storefront.
+en:
+  # Shown on every page
+  hello: Hello
+  'yes': 'yes'
+  answer: 'no'
+  'quoted: key': value
+  'on': Turned on
+  poem: |
+    # not a comment
+    not: an entry
+  trimmed: |-
+    first
+    second
+  list:
+  - a
+  - b: c
+    d: e
+  nested:
+    # Inner comment
+    deep: x
+    'off':
+      # Deeply nested
+      value: z
+# Trailing comment
+"#;
+
+    /// Describe each line of a YAML file as found by [`scan_yaml_lines()`].
+    fn scanned(text: &str) -> Vec<String> {
+        scan_yaml_lines(text)
+            .into_iter()
+            .map(|line| match line {
+                YamlLine::Entry(Some(keys)) => keys.join("/"),
+                YamlLine::Entry(None) => "entry in sequence".to_owned(),
+                YamlLine::Item => "item".to_owned(),
+                YamlLine::Comment => "comment".to_owned(),
+                YamlLine::Other => "other".to_owned(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn round_trips_rails_file() {
+        let (data, layout) = parse(RAILS_FILE, LocaleFormat::Yaml, "EN", true).unwrap();
+        assert_eq!(layout.root.as_deref(), Some("en"));
+        assert_eq!(data["yes"], "yes");
+        assert_eq!(data["answer"], "no");
+        assert_eq!(data["quoted: key"], "value");
+        assert_eq!(data["poem"], "# not a comment\nnot: an entry\n");
+        assert_eq!(data["nested"]["off"]["value"], "z");
+
+        let rendered = render(&data, LocaleFormat::Yaml, &layout, "EN").unwrap();
+        assert_eq!(rendered, RAILS_FILE);
+    }
+
+    #[test]
+    fn keeps_comments_of_renamed_root() {
+        let (data, layout) = parse(RAILS_FILE, LocaleFormat::Yaml, "EN", true).unwrap();
+        let layout = layout.for_language("DE");
+        let rendered = render(&data, LocaleFormat::Yaml, &layout, "DE").unwrap();
+        assert_eq!(rendered, RAILS_FILE.replace("\nen:\n", "\nde:\n"));
+    }
+
+    #[test]
+    fn reads_comments_by_key_path() {
+        let (trailing, comments) = read_comments(RAILS_FILE, true);
+        assert_eq!(trailing, ["# Trailing comment"]);
+        assert_eq!(
+            comments.into_iter().collect::<Vec<_>>(),
+            [
+                (
+                    String::new(),
+                    vec!["# Translations for the storefront.".to_owned()]
+                ),
+                ("hello".to_owned(), vec!["# Shown on every page".to_owned()]),
+                ("nested.deep".to_owned(), vec!["# Inner comment".to_owned()]),
+                (
+                    "nested.off.value".to_owned(),
+                    vec!["# Deeply nested".to_owned()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_block_scalars_and_sequences() {
+        let text =
+            "a:\n  b: >-\n    folded\n\n    text\n  c:\n  - item\n  - d: e\n    f: g\n  h: i\n";
+        assert_eq!(
+            scanned(text),
+            [
+                "a",
+                "a/b",
+                "other",
+                "other",
+                "other",
+                "a/c",
+                "item",
+                "item",
+                "entry in sequence",
+                "a/h",
+            ]
+        );
+
+        let (data, _) = parse(text, LocaleFormat::Yaml, "EN", true).unwrap();
+        assert_eq!(data["a"]["b"], "folded\ntext");
+    }
+
+    #[test]
+    fn splits_keys() {
+        let split = |content| split_key(content).map(|(k, v)| (k, v.to_owned()));
+        let pair = |k: &str, v: &str| Some((k.to_owned(), v.to_owned()));
+
+        assert_eq!(split("key: value"), pair("key", "value"));
+        assert_eq!(split("key:"), pair("key", ""));
+        assert_eq!(split("key: |-"), pair("key", "|-"));
+        assert_eq!(
+            split("url: http://example.com"),
+            pair("url", "http://example.com")
+        );
+        assert_eq!(split(r#""a \"b\": c": d"#), pair(r#"a "b": c"#, "d"));
+        assert_eq!(split("'it''s': x"), pair("it's", "x"));
+        assert_eq!(split("'yes': 'no'"), pair("yes", "'no'"));
+        assert_eq!(split("http://example.com"), None);
+        assert_eq!(split("'unterminated: x"), None);
+        assert_eq!(split("{a: b}"), None);
+        assert_eq!(split("plain text"), None);
+    }
+
+    #[test]
+    fn quotes_yaml_1_1_booleans() {
+        let text = "yes: no\nkey: on\nlist:\n- off\n- y: n\nother: yesterday\n";
+        assert_eq!(
+            finish_yaml(text, &LocaleLayout::default()),
+            "'yes': 'no'\nkey: 'on'\nlist:\n- 'off'\n- 'y': 'n'\nother: yesterday\n"
+        );
+    }
+
+    #[test]
+    fn formats_language_tags() {
+        assert_eq!(language_tag("DE", '_'), "de");
+        assert_eq!(language_tag("PT-BR", '-'), "pt-BR");
+        assert_eq!(language_tag("zh-hant", '_'), "zh_Hant");
+        assert_eq!(language_tag("EN-", '-'), "en");
+        assert_eq!(language_tag("sr--latn", '-'), "sr-Latn");
+    }
+}
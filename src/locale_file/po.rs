@@ -0,0 +1,692 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use super::language_tag;
+use crate::icu::{self, IcuMessage};
+use crate::key_path;
+use crate::types::{FlatLocaleData, LocaleData};
+
+/// Settings for gettext PO files, as stored in the manifest.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct GettextConfig {
+    /// Whether entries translated by ltranslate are flagged as `fuzzy`, so that gettext does not
+    /// use them until a translator has reviewed them and removed the flag.
+    #[serde(default)]
+    pub mark_fuzzy: bool,
+}
+
+/// The parts of a PO file which are not part of its data, kept so that the file can be written
+/// back the way it was laid out.
+///
+/// Entries with a `msgctxt` are stored in the data under their context, as in
+/// `{"menu": {"Open": "Öffnen"}}` (see [`data_key()`]), and entries with plural forms are stored
+/// as ICU messages with a single `plural` argument, so that their plural categories are adapted to
+/// each target language.
+#[derive(Clone, Default)]
+pub struct PoLayout {
+    /// The comments before the header entry.
+    header_comments: Vec<String>,
+    /// The fields of the header entry, such as `Project-Id-Version`, in order.
+    header_fields: Vec<(String, String)>,
+    entries: BTreeMap<String, EntryInfo>,
+    /// The lines of obsolete (`#~`) entries, which are kept at the end of the file as they are.
+    obsolete_lines: Vec<String>,
+}
+
+/// The parts of a single entry which are not part of its data, keyed by key path.
+#[derive(Clone, Default)]
+struct EntryInfo {
+    /// Comments written by translators (`# `), which belong to each PO file.
+    translator_comments: Vec<String>,
+    /// Comments extracted from the source code (`#.`) and references (`#:`), which are taken from
+    /// the source locale.
+    source_comments: Vec<String>,
+    /// Flags (`#,`) other than `fuzzy`, such as `c-format`.
+    flags: Vec<String>,
+    fuzzy: bool,
+    msgid_plural: Option<String>,
+}
+
+/// A single entry as it is written in a PO file.
+#[derive(Default)]
+struct Entry {
+    info: EntryInfo,
+    msgctxt: Option<String>,
+    msgid: Option<String>,
+    /// The `msgstr`, or each `msgstr[n]` of an entry with plural forms.
+    msgstr: Vec<String>,
+}
+
+/// An entry of locale data, as the keys of its `msgctxt` and `msgid` (see [`data_key()`]), and its
+/// value.
+type DataEntry<'a> = (Option<&'a str>, &'a str, &'a str);
+
+#[derive(Clone, Copy)]
+enum Field {
+    Msgctxt,
+    Msgid,
+    MsgidPlural,
+    Msgstr(usize),
+}
+
+impl PoLayout {
+    /// Get the layout of a new PO file translated from this one, in which nothing has been
+    /// reviewed yet.
+    pub fn for_language(&self) -> Self {
+        let mut layout = self.clone();
+        layout.entries.values_mut().for_each(|e| e.fuzzy = false);
+        layout
+    }
+
+    /// Take the comments, references, flags, and plural forms of every entry from the source
+    /// locale, as `msgmerge` does, while keeping the header, translator comments, and `fuzzy` flags
+    /// of this file.
+    pub fn update_from_source(&mut self, source_layout: &PoLayout) {
+        let mut entries = source_layout.for_language().entries;
+        for (key, entry) in entries.iter_mut() {
+            if let Some(own_entry) = self.entries.remove(key) {
+                entry.translator_comments = own_entry.translator_comments;
+                entry.fuzzy = own_entry.fuzzy;
+            }
+        }
+
+        self.entries = entries;
+    }
+
+    /// Set or clear the `fuzzy` flag of the entries which were just translated.
+    pub fn mark_translated<'a>(&mut self, keys: impl IntoIterator<Item = &'a String>, fuzzy: bool) {
+        for key in keys {
+            self.entries.entry(key.clone()).or_default().fuzzy = fuzzy;
+        }
+    }
+}
+
+/// Parse the contents of a PO or POT file of a given language.
+///
+/// Entries without a translation are left out, except in the source locale, where they are given
+/// their `msgid` (and `msgid_plural`) instead, so that a POT template can be used as the source
+/// locale. Comments extracted from the source code are used as the context of their entry, and
+/// otherwise its `msgctxt` is.
+pub fn parse(
+    text: &str,
+    language_code: &str,
+    is_source: bool,
+) -> Result<(LocaleData, PoLayout), String> {
+    let (entries, obsolete_lines) = parse_entries(text)?;
+    let mut data = LocaleData::new();
+    let mut layout = PoLayout {
+        obsolete_lines,
+        ..PoLayout::default()
+    };
+
+    for entry in entries {
+        let Some(msgid) = &entry.msgid else {
+            return Err("found an entry without a msgid".to_owned());
+        };
+
+        if msgid.is_empty() && entry.msgctxt.is_none() {
+            layout.header_comments = entry.info.translator_comments;
+            layout.header_fields = entry
+                .msgstr
+                .first()
+                .map(|h| parse_header(h))
+                .unwrap_or_default();
+            continue;
+        }
+
+        let value = entry_value(&entry, msgid, language_code, is_source);
+        let context = entry
+            .info
+            .source_comments
+            .iter()
+            .filter_map(|c| c.strip_prefix("#."))
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let msgid_key = data_key(msgid);
+        let (object, path) = match &entry.msgctxt {
+            Some(msgctxt) => {
+                let msgctxt_key = data_key(msgctxt);
+                data.entry(format!("@{msgctxt_key}"))
+                    .or_insert_with(|| JsonValue::String(msgctxt.clone()));
+                let path = key_path::join(&key_path::escape_segment(&msgctxt_key), &msgid_key);
+                match data
+                    .entry(msgctxt_key)
+                    .or_insert_with(|| JsonValue::Object(LocaleData::new()))
+                {
+                    JsonValue::Object(object) => (object, path),
+                    _ => {
+                        return Err(format!(
+                            "\"{msgctxt}\" is used as both a msgid and a msgctxt"
+                        ));
+                    }
+                }
+            }
+            None => (&mut data, key_path::escape_segment(&msgid_key)),
+        };
+
+        match object.get(&msgid_key) {
+            Some(JsonValue::Object(_)) => {
+                return Err(format!("\"{msgid}\" is used as both a msgid and a msgctxt"));
+            }
+            Some(_) => return Err(format!("msgid \"{msgid}\" is listed more than once")),
+            None => (),
+        }
+
+        // Context is only kept for entries with a value, as a key starting with `@` is only
+        // metadata when the key it describes exists.
+        if let Some(value) = value {
+            if !context.is_empty() {
+                object.insert(format!("@{msgid_key}"), JsonValue::String(context));
+            }
+
+            object.insert(msgid_key, JsonValue::String(value));
+        }
+
+        layout.entries.insert(path, entry.info);
+    }
+
+    Ok((data, layout))
+}
+
+/// Format locale data as the contents of a PO file of a given language.
+///
+/// Values which are not strings, and objects nested more than one level deep, cannot be written
+/// to a PO file, so an error is returned if there are any.
+pub fn render(data: &LocaleData, layout: &PoLayout, language_code: &str) -> Result<String, String> {
+    let entries = data_entries(data)?;
+    let mut output = String::new();
+    for comment in &layout.header_comments {
+        output.push_str(&format!("{comment}\n"));
+    }
+
+    let header = header_fields(&layout.header_fields, language_code)
+        .iter()
+        .map(|(name, value)| format!("{name}: {value}\n"))
+        .collect::<String>();
+    write_field(&mut output, "msgid", "");
+    write_field(&mut output, "msgstr", &header);
+
+    let (_, categories) = icu::gettext_plural_forms(language_code);
+    for (msgctxt_key, msgid_key, value) in entries {
+        let path = match msgctxt_key {
+            Some(msgctxt_key) => key_path::join(&key_path::escape_segment(msgctxt_key), msgid_key),
+            None => key_path::escape_segment(msgid_key),
+        };
+        let msgctxt = msgctxt_key.map(text_of_key);
+        let msgid = text_of_key(msgid_key);
+        let info = layout.entries.get(&path).cloned().unwrap_or_default();
+
+        output.push('\n');
+        for comment in info.translator_comments.iter().chain(&info.source_comments) {
+            output.push_str(&format!("{comment}\n"));
+        }
+
+        let flags = info
+            .fuzzy
+            .then_some("fuzzy")
+            .into_iter()
+            .chain(info.flags.iter().map(String::as_str))
+            .collect::<Vec<_>>();
+        if !flags.is_empty() {
+            output.push_str(&format!("#, {}\n", flags.join(", ")));
+        }
+
+        if let Some(msgctxt) = msgctxt {
+            write_field(&mut output, "msgctxt", msgctxt);
+        }
+
+        write_field(&mut output, "msgid", msgid);
+        match (&info.msgid_plural, IcuMessage::plural_forms(value)) {
+            (Some(msgid_plural), Some(forms)) => {
+                write_field(&mut output, "msgid_plural", msgid_plural);
+                for (i, category) in categories.iter().enumerate() {
+                    write_field(
+                        &mut output,
+                        &format!("msgstr[{i}]"),
+                        plural_form(&forms, category),
+                    );
+                }
+            }
+            _ => write_field(&mut output, "msgstr", value),
+        }
+    }
+
+    if !layout.obsolete_lines.is_empty() {
+        output.push('\n');
+        for line in &layout.obsolete_lines {
+            output.push_str(&format!("{line}\n"));
+        }
+    }
+
+    Ok(output)
+}
+
+/// Reduce the plural forms of translated values to the ones used by a language in gettext, so that
+/// they are the same as when they are read back from the PO file.
+pub fn normalize(data: FlatLocaleData, language_code: &str) -> FlatLocaleData {
+    let (_, categories) = icu::gettext_plural_forms(language_code);
+    data.into_iter()
+        .map(|(key, value)| {
+            let forms = value.as_str().and_then(IcuMessage::plural_forms);
+            let Some(forms) = forms else {
+                return (key, value);
+            };
+
+            let forms = categories
+                .iter()
+                .map(|c| (*c, plural_form(&forms, c)))
+                .collect::<Vec<_>>();
+            (key, JsonValue::String(IcuMessage::plural_text(&forms)))
+        })
+        .collect()
+}
+
+/// Get the value of an entry, or [`None`] if it has not been translated.
+fn entry_value(entry: &Entry, msgid: &str, language_code: &str, is_source: bool) -> Option<String> {
+    let (_, categories) = icu::gettext_plural_forms(language_code);
+    let translated = !entry.msgstr.is_empty() && entry.msgstr.iter().all(|s| !s.is_empty());
+    match &entry.info.msgid_plural {
+        None if translated => Some(entry.msgstr[0].clone()),
+        Some(_) if translated && entry.msgstr.len() == categories.len() => {
+            let forms = categories
+                .iter()
+                .zip(&entry.msgstr)
+                .map(|(c, s)| (*c, s.as_str()))
+                .collect::<Vec<_>>();
+            Some(IcuMessage::plural_text(&forms))
+        }
+        None if is_source => Some(msgid.to_owned()),
+        Some(msgid_plural) if is_source => Some(IcuMessage::plural_text(&[
+            ("one", msgid),
+            ("other", msgid_plural),
+        ])),
+        _ => None,
+    }
+}
+
+/// Get the text of a plural category, falling back to the `other` category if it is missing.
+fn plural_form<'a>(forms: &'a [(String, String)], category: &str) -> &'a str {
+    forms
+        .iter()
+        .find(|(c, _)| c == category)
+        .or_else(|| forms.iter().find(|(c, _)| c == "other"))
+        .map_or("", |(_, text)| text)
+}
+
+/// Get the object key a `msgid` or `msgctxt` is stored under in locale data.
+///
+/// Keys starting with `@` can hold metadata (see [`key_path::is_metadata_key()`]), so a backslash
+/// is put in front of any text starting with `@`, and of any text starting with a backslash so that
+/// the key can be told apart from an escaped one.
+fn data_key(text: &str) -> String {
+    match text.starts_with(['@', '\\']) {
+        true => format!("\\{text}"),
+        false => text.to_owned(),
+    }
+}
+
+/// Get the `msgid` or `msgctxt` stored under an object key, undoing [`data_key()`].
+fn text_of_key(key: &str) -> &str {
+    key.strip_prefix('\\').unwrap_or(key)
+}
+
+/// Get every entry of locale data, returning an error if any value cannot be written to a PO file.
+fn data_entries(data: &LocaleData) -> Result<Vec<DataEntry<'_>>, String> {
+    let unsupported = |path: String| {
+        format!(
+            "the value of '{path}' cannot be written to a PO file, which can only hold strings identified by a msgid and an optional msgctxt"
+        )
+    };
+
+    let mut entries = Vec::new();
+    for (key, value) in data
+        .iter()
+        .filter(|(k, _)| !key_path::is_metadata_key(k, data))
+    {
+        match value {
+            JsonValue::String(value) => entries.push((None, key.as_str(), value.as_str())),
+            JsonValue::Object(object) => {
+                for (msgid, value) in object
+                    .iter()
+                    .filter(|(k, _)| !key_path::is_metadata_key(k, object))
+                {
+                    let JsonValue::String(value) = value else {
+                        return Err(unsupported(key_path::join(
+                            &key_path::escape_segment(key),
+                            msgid,
+                        )));
+                    };
+
+                    entries.push((Some(key.as_str()), msgid.as_str(), value.as_str()));
+                }
+            }
+            _ => return Err(unsupported(key_path::escape_segment(key))),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Get the header fields of a PO file of a given language, updating the ones which describe its
+/// language and encoding.
+fn header_fields(fields: &[(String, String)], language_code: &str) -> Vec<(String, String)> {
+    let (plural_forms, _) = icu::gettext_plural_forms(language_code);
+    let required = [
+        ("Language", language_tag(language_code, '_')),
+        ("MIME-Version", "1.0".to_owned()),
+        ("Content-Type", "text/plain; charset=UTF-8".to_owned()),
+        ("Content-Transfer-Encoding", "8bit".to_owned()),
+        ("Plural-Forms", plural_forms.to_owned()),
+    ];
+
+    let mut fields = fields.to_vec();
+    for (name, value) in required {
+        match fields.iter_mut().find(|(n, _)| n == name) {
+            Some((_, existing)) => *existing = value,
+            None => fields.push((name.to_owned(), value)),
+        }
+    }
+
+    fields
+}
+
+/// Split the entries of a PO file, along with the lines of any obsolete entries.
+fn parse_entries(text: &str) -> Result<(Vec<Entry>, Vec<String>), String> {
+    let mut entries = Vec::new();
+    let mut obsolete_lines = Vec::new();
+    let mut entry = Entry::default();
+    let mut field = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with("#~") {
+            obsolete_lines.push(line.to_owned());
+            continue;
+        }
+
+        // Anything but a continuation of the msgstr ends an entry.
+        let continues_msgstr = line.starts_with("msgstr") || line.starts_with('"');
+        if !entry.msgstr.is_empty() && !continues_msgstr {
+            entries.push(std::mem::take(&mut entry));
+            field = None;
+        }
+
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(comment) = line.strip_prefix('#') {
+            match comment.chars().next() {
+                Some('.' | ':') => entry.info.source_comments.push(line.to_owned()),
+                Some(',') => {
+                    for flag in comment[1..].split(',').map(str::trim) {
+                        match flag {
+                            "" => (),
+                            "fuzzy" => entry.info.fuzzy = true,
+                            _ => entry.info.flags.push(flag.to_owned()),
+                        }
+                    }
+                }
+                // Previous msgids of fuzzy entries are only useful to gettext's own tools.
+                Some('|') => (),
+                _ => entry.info.translator_comments.push(line.to_owned()),
+            }
+
+            continue;
+        }
+
+        let (keyword, string) = match line.starts_with('"') {
+            true => (None, line),
+            false => match line.split_once(char::is_whitespace) {
+                Some((keyword, string)) => (Some(keyword), string.trim()),
+                None => return Err(format!("line {} is not valid PO syntax", i + 1)),
+            },
+        };
+
+        let Some(string) = string.strip_prefix('"').and_then(|s| s.strip_suffix('"')) else {
+            return Err(format!("line {} has an unterminated string", i + 1));
+        };
+
+        if let Some(keyword) = keyword {
+            field = Some(match keyword {
+                "msgctxt" => Field::Msgctxt,
+                "msgid" => Field::Msgid,
+                "msgid_plural" => Field::MsgidPlural,
+                "msgstr" => Field::Msgstr(0),
+                _ => keyword
+                    .strip_prefix("msgstr[")
+                    .and_then(|i| i.strip_suffix(']'))
+                    .and_then(|i| i.parse().ok())
+                    .map(Field::Msgstr)
+                    .ok_or_else(|| format!("unknown keyword '{keyword}' on line {}", i + 1))?,
+            });
+        }
+
+        let Some(field) = field else {
+            return Err(format!(
+                "line {} continues a string which was never started",
+                i + 1
+            ));
+        };
+
+        entry.field_mut(field).push_str(&unescape(string));
+    }
+
+    if entry.msgid.is_some() {
+        entries.push(entry);
+    }
+
+    Ok((entries, obsolete_lines))
+}
+
+impl Entry {
+    fn field_mut(&mut self, field: Field) -> &mut String {
+        match field {
+            Field::Msgctxt => self.msgctxt.get_or_insert_default(),
+            Field::Msgid => self.msgid.get_or_insert_default(),
+            Field::MsgidPlural => self.info.msgid_plural.get_or_insert_default(),
+            Field::Msgstr(index) => {
+                if self.msgstr.len() <= index {
+                    self.msgstr.resize(index + 1, String::new());
+                }
+
+                &mut self.msgstr[index]
+            }
+        }
+    }
+}
+
+/// Parse the `Name: value` lines of a header entry.
+fn parse_header(header: &str) -> Vec<(String, String)> {
+    header
+        .lines()
+        .filter_map(|l| l.split_once(':'))
+        .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+        .collect()
+}
+
+/// Write a keyword and its string, splitting the string after each line break as gettext does.
+fn write_field(output: &mut String, keyword: &str, text: &str) {
+    if !text.trim_end_matches('\n').contains('\n') {
+        output.push_str(&format!("{keyword} \"{}\"\n", escape(text)));
+        return;
+    }
+
+    output.push_str(&format!("{keyword} \"\"\n"));
+    for line in text.split_inclusive('\n') {
+        output.push_str(&format!("\"{}\"\n", escape(line)));
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(c @ ('"' | '\\')) => unescaped.push(c),
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"# German translations for Example.
+msgid ""
+msgstr ""
+"Project-Id-Version: Example 1.0\n"
+"Language: de\n"
+"MIME-Version: 1.0\n"
+"Content-Type: text/plain; charset=UTF-8\n"
+"Content-Transfer-Encoding: 8bit\n"
+"Plural-Forms: nplurals=2; plural=(n != 1);\n"
+"#;
+
+    /// Parse a German PO file and check that rendering it again gives exactly the same text.
+    fn assert_round_trip(body: &str) -> LocaleData {
+        let text = format!("{HEADER}{body}");
+        let (data, layout) = parse(&text, "DE", false).unwrap();
+        assert_eq!(render(&data, &layout, "DE").unwrap(), text);
+        data
+    }
+
+    #[test]
+    fn round_trips_typical_file() {
+        let data = assert_round_trip(
+            r#"
+# Shown on the start page.
+#. The greeting at the top of the page
+#: src/main.c:12
+#, c-format
+msgid "Hello, %s!"
+msgstr "Hallo, %s!"
+
+msgid ""
+"First line\n"
+"Second line with \"quotes\"\n"
+msgstr ""
+"Erste Zeile\n"
+"Zweite Zeile mit \"Anführungszeichen\"\n"
+
+#~ msgid "Removed"
+#~ msgstr "Entfernt"
+"#,
+        );
+
+        assert_eq!(data["Hello, %s!"], "Hallo, %s!");
+        assert_eq!(data["@Hello, %s!"], "The greeting at the top of the page");
+    }
+
+    #[test]
+    fn round_trips_msgctxt() {
+        let data = assert_round_trip(
+            r#"
+msgctxt "menu"
+msgid "Open"
+msgstr "Öffnen"
+
+msgctxt "state"
+msgid "Open"
+msgstr "Geöffnet"
+"#,
+        );
+
+        assert_eq!(data["menu"]["Open"], "Öffnen");
+        assert_eq!(data["state"]["Open"], "Geöffnet");
+        assert!(key_path::is_metadata_key("@menu", &data));
+    }
+
+    #[test]
+    fn round_trips_plurals() {
+        let data = assert_round_trip(
+            r#"
+msgid "One file"
+msgid_plural "%d files"
+msgstr[0] "Eine Datei"
+msgstr[1] "%d Dateien"
+"#,
+        );
+
+        let forms = IcuMessage::plural_forms(data["One file"].as_str().unwrap()).unwrap();
+        assert_eq!(plural_form(&forms, "one"), "Eine Datei");
+        assert_eq!(plural_form(&forms, "other"), "%d Dateien");
+    }
+
+    #[test]
+    fn round_trips_fuzzy_entries() {
+        let data = assert_round_trip(
+            r#"
+#, fuzzy, python-format
+msgid "Save"
+msgstr "Speichern"
+"#,
+        );
+
+        assert_eq!(data["Save"], "Speichern");
+    }
+
+    #[test]
+    fn round_trips_msgids_starting_with_at_sign() {
+        let data = assert_round_trip(
+            r#"
+msgid "@mention"
+msgstr "@Erwähnung"
+
+msgid "mention"
+msgstr "Erwähnung"
+
+msgctxt "@user"
+msgid "\\back"
+msgstr "\\zurück"
+"#,
+        );
+
+        assert_eq!(data[r"\@mention"], "@Erwähnung");
+        assert_eq!(data["mention"], "Erwähnung");
+        assert_eq!(data[r"\@user"][r"\\back"], r"\zurück");
+        assert!(!key_path::is_metadata_key(r"\@mention", &data));
+    }
+
+    #[test]
+    fn leaves_out_untranslated_entries_and_their_context() {
+        let text = format!("{HEADER}\n#. A button\nmsgid \"Close\"\nmsgstr \"\"\n");
+        let (data, _) = parse(&text, "DE", false).unwrap();
+        assert!(data.is_empty());
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use color_print::ceprintln;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::helper_functions::{create_parent_directories_if_not_exists, hash_text};
+use crate::types::{FlatLocaleData, LocaleDataDiff, LocaleDocument, LocaleManifest};
+use crate::{LOCKFILE_PATH, SOURCE_LOCALE_HISTORY_PATH, exit, key_path};
+
+/// The locked entries of a single language, keyed by key path.
+type LockedKeys = BTreeMap<String, LockedKey>;
+
+/// A record of which source value every translated key was made from, kept separately for each
+/// target language so that each language can be brought up to date on its own.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    languages: BTreeMap<String, LockedKeys>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct LockedKey {
+    /// The hash of the source value the translation was made from.
+    source: String,
+    /// The hash of the translation as it was last written or accepted, used to notice when it has
+    /// been edited by hand. Projects upgraded from the source locale snapshot start without one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    translation: Option<String>,
+    /// Set once the translation has been edited by hand, after which it is no longer replaced.
+    #[serde(default, skip_serializing_if = "is_false")]
+    edited: bool,
+    /// Set when the source value of an edited translation has changed, until it is reviewed.
+    #[serde(default, skip_serializing_if = "is_false")]
+    review: bool,
+}
+
+impl Lockfile {
+    /// Read the lockfile of the current project.
+    ///
+    /// Projects created before the lockfile existed only have a snapshot of the source locale, so
+    /// their lockfile is created from it, on the assumption that every enabled language was
+    /// translated from that snapshot. The snapshot is removed the next time the lockfile is
+    /// written.
+    pub fn read(manifest_data: &LocaleManifest) -> Option<Self> {
+        let Ok(lockfile) = std::fs::read_to_string(LOCKFILE_PATH) else {
+            return Self::from_source_history(manifest_data);
+        };
+
+        let Ok(lockfile) = serde_json::from_str(&lockfile) else {
+            exit!("Failed to parse lockfile <g>'{}'</>.", LOCKFILE_PATH);
+        };
+
+        Some(lockfile)
+    }
+
+    fn from_source_history(manifest_data: &LocaleManifest) -> Option<Self> {
+        let history_data = LocaleDocument::read(
+            PathBuf::from(SOURCE_LOCALE_HISTORY_PATH),
+            manifest_data.source_language.clone(),
+            true,
+        )?
+        .flat_data();
+
+        ceprintln!(
+            "Found <g>'{}'</> from an older version of ltranslate. It will be replaced by <g>'{}'</> the next time the project is updated.",
+            SOURCE_LOCALE_HISTORY_PATH,
+            LOCKFILE_PATH
+        );
+
+        let mut lockfile = Lockfile::default();
+        for lang in &manifest_data.languages {
+            let locked = history_data
+                .iter()
+                .map(|(k, v)| {
+                    let entry = LockedKey {
+                        source: hash_value(v),
+                        ..LockedKey::default()
+                    };
+                    (k.clone(), entry)
+                })
+                .collect();
+            lockfile.languages.insert(lang.code.clone(), locked);
+        }
+
+        Some(lockfile)
+    }
+
+    /// Compare the values of a foreign locale against the translations last written to it, marking
+    /// any which differ as edited by hand, and returning their keys.
+    ///
+    /// Keys which have been deleted from the locale are forgotten, so that they are translated
+    /// again.
+    pub fn detect_edits(
+        &mut self,
+        language_code: &str,
+        locale_data: &FlatLocaleData,
+    ) -> Vec<String> {
+        let Some(locked) = self.languages.get_mut(language_code) else {
+            return Vec::new();
+        };
+
+        locked.retain(|k, _| locale_data.contains_key(k));
+
+        let mut edited_keys = Vec::new();
+        for (key, entry) in locked.iter_mut() {
+            let hash = hash_value(&locale_data[key]);
+            match &entry.translation {
+                Some(translation) if *translation != hash => {
+                    entry.translation = Some(hash);
+                    entry.edited = true;
+                    // Editing a value which was waiting for review counts as reviewing it.
+                    entry.review = false;
+                    edited_keys.push(key.clone());
+                }
+                Some(_) => (),
+                None => entry.translation = Some(hash),
+            }
+        }
+
+        edited_keys
+    }
+
+    /// Compare the current source locale against the source values a language was last translated
+    /// from, returning [`None`] if the language is up to date.
+    ///
+    /// Keys which have been edited by hand are never retranslated; if their source value has
+    /// changed, they are listed in [`LocaleDataDiff::review`] instead. A new key whose source value
+    /// is the same as that of a removed key is treated as the removed key being renamed or moved.
+    pub fn diff(&self, language_code: &str, current: &FlatLocaleData) -> Option<LocaleDataDiff> {
+        let empty = LockedKeys::new();
+        let locked = self.languages.get(language_code).unwrap_or(&empty);
+
+        let mut changed_or_added = FlatLocaleData::new();
+        let mut review = Vec::new();
+        for (key, value) in current {
+            match locked.get(key) {
+                Some(entry) if entry.source == hash_value(value) => (),
+                Some(entry) if entry.edited => review.push(key.clone()),
+                _ => {
+                    changed_or_added.insert(key.clone(), value.clone());
+                }
+            }
+        }
+
+        let mut removed = locked
+            .keys()
+            .filter(|k| !current.contains_key(*k))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // When several removed keys have the same value, one with the same last segment is
+        // preferred, so that moving a group of keys keeps each translation with its own key.
+        let mut renamed = Vec::new();
+        for (key, value) in &changed_or_added {
+            if locked.contains_key(key) {
+                continue;
+            }
+
+            let hash = hash_value(value);
+            let mut candidates = removed
+                .iter()
+                .enumerate()
+                .filter(|(_, old_key)| locked[*old_key].source == hash);
+            let first_candidate = candidates.clone().next();
+            let Some((i, _)) = candidates
+                .find(|(_, old_key)| key_path::last_segment(old_key) == key_path::last_segment(key))
+                .or(first_candidate)
+            else {
+                continue;
+            };
+
+            renamed.push((removed.remove(i), key.clone()));
+        }
+
+        changed_or_added.retain(|k, _| !renamed.iter().any(|(_, new_key)| new_key == k));
+        if changed_or_added.is_empty()
+            && removed.is_empty()
+            && renamed.is_empty()
+            && review.is_empty()
+        {
+            return None;
+        }
+
+        Some(LocaleDataDiff {
+            changed_or_added,
+            removed,
+            renamed,
+            review,
+        })
+    }
+
+    /// Check whether a key of a language has been translated before, regardless of whether its
+    /// source value has changed since.
+    pub fn is_locked(&self, language_code: &str, key: &str) -> bool {
+        self.languages
+            .get(language_code)
+            .is_some_and(|l| l.contains_key(key))
+    }
+
+    /// Get the keys of a language which were edited by hand and are waiting to be reviewed.
+    pub fn review_keys(&self, language_code: &str) -> Vec<String> {
+        self.languages
+            .get(language_code)
+            .map(|l| {
+                l.iter()
+                    .filter(|(_, entry)| entry.review)
+                    .map(|(k, _)| k.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Record that every value in `translated_data` was just written to a language, translated from
+    /// its value in `source_data`.
+    pub fn record(
+        &mut self,
+        language_code: &str,
+        source_data: &FlatLocaleData,
+        translated_data: &FlatLocaleData,
+    ) {
+        let locked = self.languages.entry(language_code.to_owned()).or_default();
+        for (key, translation) in translated_data {
+            if let Some(value) = source_data.get(key) {
+                let entry = LockedKey {
+                    source: hash_value(value),
+                    translation: Some(hash_value(translation)),
+                    ..LockedKey::default()
+                };
+                locked.insert(key.clone(), entry);
+            }
+        }
+    }
+
+    /// Forget the given keys of a language, so that they are translated again by the next update.
+    pub fn forget<'a>(&mut self, language_code: &str, keys: impl IntoIterator<Item = &'a String>) {
+        if let Some(locked) = self.languages.get_mut(language_code) {
+            for key in keys {
+                locked.remove(key);
+            }
+        }
+    }
+
+    /// Forget every translation of a language which was not edited by hand, so that the next update
+    /// translates them again.
+    pub fn forget_unedited(&mut self, language_code: &str) {
+        if let Some(locked) = self.languages.get_mut(language_code) {
+            locked.retain(|_, entry| entry.edited);
+        }
+    }
+
+    /// Record the result of applying `diff` to a language, where `translated_data` holds the
+    /// values which were translated successfully.
+    ///
+    /// Renamed keys keep their records, changed keys which were not translated are forgotten, so
+    /// that they are tried again by the next update, and edited keys whose source value changed are
+    /// marked for review.
+    pub fn apply(
+        &mut self,
+        language_code: &str,
+        source_data: &FlatLocaleData,
+        diff: &LocaleDataDiff,
+        translated_data: &FlatLocaleData,
+    ) {
+        if let Some(locked) = self.languages.get_mut(language_code) {
+            for (old_key, new_key) in &diff.renamed {
+                if let Some(entry) = locked.remove(old_key) {
+                    locked.insert(new_key.clone(), entry);
+                }
+            }
+        }
+
+        self.forget(language_code, &diff.removed);
+        self.forget(language_code, diff.changed_or_added.keys());
+        self.record(language_code, source_data, translated_data);
+
+        let Some(locked) = self.languages.get_mut(language_code) else {
+            return;
+        };
+
+        for key in &diff.review {
+            if let (Some(entry), Some(value)) = (locked.get_mut(key), source_data.get(key)) {
+                entry.source = hash_value(value);
+                entry.review = true;
+            }
+        }
+    }
+
+    /// Mark the given keys of a language as reviewed, keeping their edited translations.
+    pub fn accept_review<'a>(
+        &mut self,
+        language_code: &str,
+        keys: impl IntoIterator<Item = &'a String>,
+    ) {
+        if let Some(locked) = self.languages.get_mut(language_code) {
+            for key in keys {
+                if let Some(entry) = locked.get_mut(key) {
+                    entry.review = false;
+                }
+            }
+        }
+    }
+
+    /// Remove every record of a language which is no longer enabled.
+    pub fn remove_language(&mut self, language_code: &str) {
+        self.languages.remove(language_code);
+    }
+
+    /// Write the lockfile to [`LOCKFILE_PATH`], or a different path if specified.
+    ///
+    /// When the lockfile is written to its own path, the source locale snapshot it replaces is
+    /// removed, if there is one.
+    pub fn write_out(&self, override_path: Option<PathBuf>) {
+        let replaces_history = override_path.is_none();
+        let path = override_path.unwrap_or_else(|| PathBuf::from(LOCKFILE_PATH));
+        let Ok(formatted_data) = serde_json::to_string_pretty(self) else {
+            exit!("Unknown error occured when serializing lockfile.");
+        };
+
+        create_parent_directories_if_not_exists(&path);
+        if std::fs::write(&path, formatted_data).is_err() {
+            exit!("Failed to write lockfile. Ensure that the file permissions are set correctly.");
+        }
+
+        if replaces_history {
+            remove_source_history();
+        }
+    }
+}
+
+/// Remove the source locale snapshot used by projects created before the lockfile existed, once the
+/// lockfile which replaces it has been written.
+pub fn remove_source_history() {
+    let _ = std::fs::remove_file(SOURCE_LOCALE_HISTORY_PATH);
+}
+
+/// Hash a value, so that changes to it can be detected without storing the value itself.
+fn hash_value(value: &JsonValue) -> String {
+    let text = match value {
+        JsonValue::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+
+    hash_text(&text)
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
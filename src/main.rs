@@ -1,20 +1,50 @@
+mod backend;
+mod check;
+mod glossary;
 mod helper_functions;
+mod icu;
 mod interact;
+mod key_path;
+mod locale_file;
+mod lockfile;
+mod metadata;
+mod placeholder;
+mod transaction;
 mod types;
+mod verbatim;
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
-use clap::{Arg, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 use color_print::{ceprintln, cformat};
 
-use types::{DeepLContext, LanguageDiff, LocaleDataDiff, LocaleDocument, LocaleManifest};
+use backend::{
+    BACKEND_ENV_VAR, BackendKind, CachedBackend, DEFAULT_CONCURRENCY, Formality, JournaledBackend,
+    RequestSettings, RetryPolicy, TranslationBackend, journal,
+};
+use check::CheckReport;
+use glossary::{Glossary, SyncedGlossary};
+use locale_file::LocaleFormat;
+use lockfile::Lockfile;
+use transaction::Transaction;
+use types::{Language, LanguageDiff, LocaleDocument, LocaleManifest};
 
+use crate::helper_functions::{file_exists, run_concurrently};
 use crate::{interact::ProjectSetting, types::AppData};
 
 const APP_DIR_PATH: &str = "./ltranslate";
 const LANG_DIR_PATH: &str = "./lang";
 const MANIFEST_PATH: &str = "./ltranslate/manifest.toml";
+const LOCKFILE_PATH: &str = "./ltranslate/lock.json";
+/// A snapshot of the source locale kept by projects created before the lockfile existed, which is
+/// only read to create their lockfile.
 const SOURCE_LOCALE_HISTORY_PATH: &str = "./ltranslate/source-history.json";
+const TRANSLATION_MEMORY_PATH: &str = "./ltranslate/translation-memory.json";
+const STAGING_DIR_PATH: &str = "./ltranslate/.staging";
+const JOURNAL_PATH: &str = "./ltranslate/journal.json";
+const GLOSSARY_DIR_PATH: &str = "./ltranslate/glossaries";
+const CONTEXT_PATH: &str = "./ltranslate/context.json";
 
 #[macro_export]
 macro_rules! exit {
@@ -26,6 +56,15 @@ macro_rules! exit {
 
 fn main() {
     let Ok(_) = ctrlc::set_handler(move || {
+        // The first Ctrl-C during a journaled translation lets the batches in progress finish, so
+        // that they are not billed without being recorded.
+        if journal::request_cancel() {
+            ceprintln!(
+                "<y>Stopping after the current batch...</> Press Ctrl-C again to exit immediately."
+            );
+            return;
+        }
+
         exit!(
             "Exited ltranslate. You may have to run the <m>'reset'</> command to return your terminal to its normal state."
         );
@@ -37,12 +76,110 @@ fn main() {
         .author("Lowell Thoerner, contact@lthoerner.com")
         .version(env!("CARGO_PKG_VERSION"))
         .about("A utility for translating locale files using DeepL.")
+        .arg(
+            Arg::new("backend")
+                .long("backend")
+                .global(true)
+                .value_parser(BackendKind::IDENTIFIERS)
+                .help(Some("Choose the translation backend; 'pseudo' generates offline pseudo-translations for testing (can also be set with LTRANSLATE_BACKEND)"))
+        )
+        .arg(
+            Arg::new("max_retries")
+                .long("max-retries")
+                .global(true)
+                .value_parser(clap::value_parser!(u32))
+                .help(Some("Retry requests which fail because of a temporary problem up to this many times (defaults to the project setting, or 5)"))
+        )
+        .arg(
+            Arg::new("max_wait")
+                .long("max-wait")
+                .value_name("SECONDS")
+                .global(true)
+                .value_parser(clap::value_parser!(u64))
+                .help(Some("Wait at most this long between retries (defaults to the project setting, or 60)"))
+        )
+        .arg(
+            Arg::new("concurrency")
+                .long("concurrency")
+                .global(true)
+                .value_parser(clap::value_parser!(u32).range(1..))
+                .help(Some("Send at most this many requests at once (defaults to the project setting, or 4)"))
+        )
+        .arg(
+            Arg::new("no_cache")
+                .long("no-cache")
+                .global(true)
+                .action(ArgAction::SetTrue)
+                .help(Some("Send every value to the translation service instead of reusing translations from the project's translation memory"))
+        )
         .subcommand(
             Command::new("project")
                 .about("Use project mode to automatically translate locales for you")
-                .subcommand(Command::new("setup").about("Set up a new project and point it at your existing English locale file"))
-                .subcommand(Command::new("manage").about("Alter project settings such as enabled languages"))
-                .subcommand(Command::new("update").about("Check the English locale file for changes and update all other locales accordingly"))
+                .subcommand(
+                    Command::new("setup")
+                        .about("Set up a new project and point it at your existing source locale file")
+                        .arg(Arg::new("source").long("source").value_name("PATH").help(Some("Path to the source locale file")))
+                        .arg(Arg::new("source_lang").long("source-lang").value_name("CODE").help(Some("Language of the source locale file")))
+                        .arg(Arg::new("lang").long("lang").value_name("CODE[=PATH]").action(ArgAction::Append).help(Some("Enable a target language, optionally with its output path (can be repeated)")))
+                        .arg(Arg::new("yes").short('y').long("yes").action(ArgAction::SetTrue).help(Some("Skip confirmation prompts")))
+                )
+                .subcommand(
+                    Command::new("manage")
+                        .about("Alter project settings such as enabled languages")
+                        .arg(Arg::new("set_source").long("set-source").value_name("PATH").help(Some("Change the path of the source locale file")))
+                        .arg(Arg::new("set_source_lang").long("set-source-lang").value_name("CODE").help(Some("Change the language of the source locale file")))
+                        .arg(Arg::new("add_lang").long("add-lang").value_name("CODE[=PATH]").action(ArgAction::Append).help(Some("Enable a target language, optionally with its output path (can be repeated)")))
+                        .arg(Arg::new("remove_lang").long("remove-lang").value_name("CODE").action(ArgAction::Append).help(Some("Disable a target language (can be repeated)")))
+                        .arg(Arg::new("set_formality").long("set-formality").value_name("CODE=LEVEL").action(ArgAction::Append).help(Some("Change how formal translations into a target language are: default, more, less, prefer_more, or prefer_less (can be repeated)")))
+                        .arg(Arg::new("dry_run").long("dry-run").action(ArgAction::SetTrue).help(Some("Show what would be translated and how many characters it would cost, without calling the translation service or writing any files")))
+                )
+                .subcommand(
+                    Command::new("update")
+                        .about("Check the source locale file for changes and update all other locales accordingly")
+                        .arg(Arg::new("dry_run").long("dry-run").action(ArgAction::SetTrue).help(Some("Show what would be translated and how many characters it would cost, without calling the translation service or writing any files")))
+                )
+                .subcommand(
+                    Command::new("check")
+                        .about("Check whether all locales are up to date without translating anything (useful for CI)")
+                        .arg(Arg::new("json").long("json").action(ArgAction::SetTrue).help(Some("Also print a machine-readable JSON report to stdout")))
+                )
+                .subcommand(
+                    Command::new("review")
+                        .about("List values edited by hand whose source text has changed, and keep or retranslate them")
+                        .arg(Arg::new("lang").long("lang").value_name("CODE").action(ArgAction::Append).help(Some("Only review the given language (can be repeated)")))
+                        .arg(Arg::new("accept").long("accept").action(ArgAction::SetTrue).conflicts_with("retranslate").help(Some("Keep the edited values as they are")))
+                        .arg(Arg::new("retranslate").long("retranslate").action(ArgAction::SetTrue).help(Some("Discard the edited values, so that the next update retranslates them")))
+                )
+                .arg_required_else_help(true)
+        )
+        .subcommand(
+            Command::new("cache")
+                .about("Manage the project's translation memory")
+                .subcommand(Command::new("prune").about("Remove translations which are no longer needed by the current source locale and languages"))
+                .arg_required_else_help(true)
+        )
+        .subcommand(
+            Command::new("glossary")
+                .about("Manage glossaries of terms which should always be translated the same way, such as product names")
+                .subcommand(
+                    Command::new("add")
+                        .about("Add a term to the glossary of a target language, or change its translation")
+                        .arg(Arg::new("source_term").required(true).index(1))
+                        .arg(Arg::new("target_term").required(true).index(2))
+                        .arg(Arg::new("lang").long("lang").value_name("CODE").required(true).help(Some("Target language of the glossary")))
+                )
+                .subcommand(
+                    Command::new("remove")
+                        .about("Remove a term from the glossary of a target language")
+                        .arg(Arg::new("source_term").required(true).index(1))
+                        .arg(Arg::new("lang").long("lang").value_name("CODE").required(true).help(Some("Target language of the glossary")))
+                )
+                .subcommand(
+                    Command::new("list")
+                        .about("List the terms in each glossary, and whether they have been synced")
+                        .arg(Arg::new("lang").long("lang").value_name("CODE").help(Some("Only list the glossary of the given target language")))
+                )
+                .subcommand(Command::new("sync").about("Upload glossaries which have changed to the translation service, so that they are applied to later translations"))
                 .arg_required_else_help(true)
         )
         .subcommand(
@@ -51,13 +188,25 @@ fn main() {
                 .arg(Arg::new("input_file").required(true).index(1))
                 .arg(Arg::new("output_file").required(true).index(2))
                 .arg(Arg::new("language").short('l').long("language").help(Some("Specify the traget language instead of picking it from a list (useful for scripts)")))
+                .arg(Arg::new("source_language").short('s').long("source-language").help(Some("Specify the language of the input file (defaults to English)")))
+                .arg(Arg::new("formality").long("formality").value_parser(Formality::IDENTIFIERS).help(Some("Make the translation more or less formal, if the target language supports it")))
+                .arg(Arg::new("yes").short('y').long("yes").action(ArgAction::SetTrue).help(Some("Skip the confirmation prompt")))
+                .arg(Arg::new("dry_run").long("dry-run").action(ArgAction::SetTrue).help(Some("Show what would be translated and how many characters it would cost, without calling the translation service or writing any files")))
                 .arg_required_else_help(true)
         )
         .arg_required_else_help(true)
         .get_matches();
 
     let _ = dotenvy::dotenv();
-    let deepl = DeepLContext::connect();
+    // The backend is only connected to by commands which translate, so that commands such as
+    // `project check` work without an API key.
+    let backend_kind = select_backend(args.get_one::<String>("backend"));
+    let use_cache = !args.get_flag("no_cache");
+    let request_settings = select_request_settings(
+        args.get_one::<u32>("max_retries").copied(),
+        args.get_one::<u64>("max_wait").copied(),
+        args.get_one::<u32>("concurrency").map(|c| *c as usize),
+    );
 
     let Some((subcommand_name, subcommand_args)) = args.subcommand() else {
         exit!("Missing subcommand. This is likely a logic bug.");
@@ -65,14 +214,119 @@ fn main() {
 
     match subcommand_name {
         "project" => {
-            let Some((project_sub, _project_args)) = subcommand_args.subcommand() else {
+            let Some((project_sub, project_args)) = subcommand_args.subcommand() else {
                 exit!("Missing subcommand. This is likely a logic bug.");
             };
 
             match project_sub {
-                "setup" => set_up_project(&deepl),
-                "manage" => manage_project(&deepl),
-                "update" => update_project(&deepl),
+                "setup" => set_up_project(
+                    connect_project_backend(
+                        backend_kind,
+                        "project setup",
+                        false,
+                        use_cache,
+                        request_settings,
+                    )
+                    .as_ref(),
+                    SetupFlags {
+                        source_path: project_args.get_one::<String>("source").map(PathBuf::from),
+                        source_language: project_args.get_one::<String>("source_lang").cloned(),
+                        languages: language_flags(project_args, "lang"),
+                        assume_yes: project_args.get_flag("yes"),
+                    },
+                    request_settings.concurrency,
+                ),
+                "manage" => manage_project(
+                    connect_project_backend(
+                        backend_kind,
+                        "project manage",
+                        project_args.get_flag("dry_run"),
+                        use_cache,
+                        request_settings,
+                    )
+                    .as_ref(),
+                    ManageFlags {
+                        source_path: project_args
+                            .get_one::<String>("set_source")
+                            .map(PathBuf::from),
+                        source_language: project_args.get_one::<String>("set_source_lang").cloned(),
+                        added_languages: language_flags(project_args, "add_lang"),
+                        removed_languages: project_args
+                            .get_many::<String>("remove_lang")
+                            .map(|codes| codes.cloned().collect())
+                            .unwrap_or_default(),
+                        formality: formality_flags(project_args),
+                    },
+                    project_args.get_flag("dry_run"),
+                    request_settings.concurrency,
+                ),
+                "update" => {
+                    let dry_run = project_args.get_flag("dry_run");
+                    update_project(
+                        connect_project_backend(
+                            backend_kind,
+                            "project update",
+                            dry_run,
+                            use_cache,
+                            request_settings,
+                        )
+                        .as_ref(),
+                        dry_run,
+                        use_cache,
+                        request_settings.concurrency,
+                    )
+                }
+                "check" => check_project(project_args.get_flag("json")),
+                "review" => review_project(
+                    &project_args
+                        .get_many::<String>("lang")
+                        .map(|codes| codes.cloned().collect::<Vec<_>>())
+                        .unwrap_or_default(),
+                    project_args.get_flag("accept"),
+                    project_args.get_flag("retranslate"),
+                ),
+                _ => exit!("Unknown subcommand. This is likely a logic bug."),
+            }
+        }
+        "cache" => {
+            let Some((cache_sub, _cache_args)) = subcommand_args.subcommand() else {
+                exit!("Missing subcommand. This is likely a logic bug.");
+            };
+
+            match cache_sub {
+                "prune" => prune_cache(),
+                _ => exit!("Unknown subcommand. This is likely a logic bug."),
+            }
+        }
+        "glossary" => {
+            let Some((glossary_sub, glossary_args)) = subcommand_args.subcommand() else {
+                exit!("Missing subcommand. This is likely a logic bug.");
+            };
+
+            let language_code = || {
+                glossary_args
+                    .get_one::<String>("lang")
+                    .map(String::as_str)
+                    .unwrap_or_default()
+            };
+            let term = |id: &str| {
+                glossary_args
+                    .get_one::<String>(id)
+                    .map(String::as_str)
+                    .unwrap_or_default()
+            };
+            match glossary_sub {
+                "add" => edit_glossary(
+                    language_code(),
+                    term("source_term"),
+                    Some(term("target_term")),
+                ),
+                "remove" => edit_glossary(language_code(), term("source_term"), None),
+                "list" => list_glossaries(
+                    backend_kind,
+                    glossary_args.get_one::<String>("lang").map(String::as_str),
+                ),
+                "sync" => sync_glossaries(backend_kind.connect(false, request_settings).as_ref()),
                 _ => exit!("Unknown subcommand. This is likely a logic bug."),
             }
         }
@@ -91,206 +345,1416 @@ fn main() {
                 exit!("Missing output file. This is likely a logic bug.");
             };
 
-            let target_language = subcommand_args.get_one::<String>("language").cloned();
-            translate_interactive(&deepl, input_file, output_file, target_language);
+            let dry_run = subcommand_args.get_flag("dry_run");
+            translate_interactive(
+                backend_kind.connect(dry_run, request_settings).as_ref(),
+                input_file,
+                output_file,
+                TranslateFlags {
+                    source_language: subcommand_args
+                        .get_one::<String>("source_language")
+                        .cloned(),
+                    target_language: subcommand_args.get_one::<String>("language").cloned(),
+                    formality: subcommand_args
+                        .get_one::<String>("formality")
+                        .and_then(|f| Formality::from_identifier(f))
+                        .unwrap_or_default(),
+                    assume_yes: subcommand_args.get_flag("yes"),
+                },
+                dry_run,
+            );
         }
         _ => exit!("Unknown subcommand. This is likely a logic bug."),
     }
 }
 
-/// Prompt the user to set up the project, run initial translations, and write the app data to its
-/// directory.
-fn set_up_project(deepl_context: &DeepLContext) {
-    let mut manifest_data = LocaleManifest::from_user_setup();
-    let target_languages = interact::select_target_languages(deepl_context, None);
+/// Determine which translation backend to use, preferring the `--backend` flag over the
+/// [`BACKEND_ENV_VAR`] environment variable, and falling back to DeepL.
+fn select_backend(backend_arg: Option<&String>) -> BackendKind {
+    let identifier = match backend_arg {
+        Some(identifier) => identifier.clone(),
+        None => match std::env::var(BACKEND_ENV_VAR) {
+            Ok(identifier) => identifier,
+            Err(_) => return BackendKind::DeepL,
+        },
+    };
 
-    interact::select_output_locale_all(&target_languages)
-        .into_iter()
-        .for_each(|(lang, path)| {
-            let _ = manifest_data.locale_paths.insert(lang, path);
-        });
-    target_languages
+    let Some(kind) = BackendKind::from_identifier(&identifier) else {
+        exit!(
+            "Unknown translation backend <r>'{}'</>. Valid options are: {}.",
+            identifier,
+            BackendKind::IDENTIFIERS.join(", ")
+        );
+    };
+
+    kind
+}
+
+/// Determine how requests are sent, preferring the `--max-retries`, `--max-wait`, and
+/// `--concurrency` flags over the project manifest, and falling back to the defaults.
+fn select_request_settings(
+    max_retries: Option<u32>,
+    max_wait_seconds: Option<u64>,
+    concurrency: Option<usize>,
+) -> RequestSettings {
+    let mut request_settings = match LocaleManifest::get_existing() {
+        Some(manifest_data) => RequestSettings {
+            retry_policy: manifest_data.retry,
+            concurrency: manifest_data.concurrency,
+        },
+        None => RequestSettings {
+            retry_policy: RetryPolicy::default(),
+            concurrency: DEFAULT_CONCURRENCY,
+        },
+    };
+
+    if let Some(max_retries) = max_retries {
+        request_settings.retry_policy.max_retries = max_retries;
+    }
+
+    if let Some(max_wait_seconds) = max_wait_seconds {
+        request_settings.retry_policy.max_wait_seconds = max_wait_seconds;
+    }
+
+    if let Some(concurrency) = concurrency {
+        request_settings.concurrency = concurrency;
+    }
+
+    request_settings
+}
+
+/// Exit if the user stopped the translations of `command` with Ctrl-C, once every translation
+/// still in progress has finished.
+fn exit_if_cancelled(command: &str) {
+    if journal::is_cancelled() {
+        exit!(
+            "Translation was stopped. No files were changed, and finished translations were saved to <g>'{}'</>, so running <m>'ltranslate {}'</> again will resume where this run left off.",
+            JOURNAL_PATH,
+            command
+        );
+    }
+}
+
+/// Connect to a backend for use in project mode, wrapping it in a [`CachedBackend`] unless
+/// `use_cache` is unset, and in a [`JournaledBackend`] for `command` unless `dry_run` is set.
+fn connect_project_backend(
+    kind: BackendKind,
+    command: &str,
+    dry_run: bool,
+    use_cache: bool,
+    request_settings: RequestSettings,
+) -> Box<dyn TranslationBackend> {
+    let backend = kind.connect(dry_run, request_settings);
+    let backend = match dry_run {
+        true => backend,
+        false => Box::new(JournaledBackend::new(backend, kind.identifier(), command)),
+    };
+
+    match use_cache {
+        true => Box::new(CachedBackend::new(backend, kind.identifier(), !dry_run)),
+        false => backend,
+    }
+}
+
+/// Values for `project setup` which were provided as flags instead of through prompts.
+struct SetupFlags {
+    source_path: Option<PathBuf>,
+    source_language: Option<String>,
+    languages: Vec<LanguageFlag>,
+    assume_yes: bool,
+}
+
+/// Values for `project manage` which were provided as flags instead of through prompts.
+struct ManageFlags {
+    source_path: Option<PathBuf>,
+    source_language: Option<String>,
+    added_languages: Vec<LanguageFlag>,
+    removed_languages: Vec<String>,
+    formality: Vec<(String, Formality)>,
+}
+
+/// Values for `translate` which were provided as flags instead of through prompts.
+struct TranslateFlags {
+    source_language: Option<String>,
+    target_language: Option<String>,
+    formality: Formality,
+    assume_yes: bool,
+}
+
+/// A target language given on the command line as `CODE` or `CODE=PATH`.
+struct LanguageFlag {
+    code: String,
+    path: Option<PathBuf>,
+}
+
+impl ManageFlags {
+    fn is_empty(&self) -> bool {
+        self.source_path.is_none()
+            && self.source_language.is_none()
+            && self.added_languages.is_empty()
+            && self.removed_languages.is_empty()
+            && self.formality.is_empty()
+    }
+}
+
+/// Parse every `CODE[=PATH]` value given for a language flag.
+fn language_flags(args: &ArgMatches, id: &str) -> Vec<LanguageFlag> {
+    args.get_many::<String>(id)
+        .map(|values| {
+            values
+                .map(|value| match value.split_once('=') {
+                    Some((code, path)) => LanguageFlag {
+                        code: code.to_owned(),
+                        path: Some(PathBuf::from(path)),
+                    },
+                    None => LanguageFlag {
+                        code: value.clone(),
+                        path: None,
+                    },
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parse every `CODE=LEVEL` value given for `--set-formality`, exiting if any is malformed.
+fn formality_flags(args: &ArgMatches) -> Vec<(String, Formality)> {
+    args.get_many::<String>("set_formality")
+        .map(|values| {
+            values
+                .map(|value| {
+                    let Some((code, formality)) = value
+                        .split_once('=')
+                        .and_then(|(c, f)| Some((c, Formality::from_identifier(f)?)))
+                    else {
+                        exit!(
+                            "Invalid formality <r>'{}'</>. Use <m>CODE=LEVEL</>, where the level is one of: {}.",
+                            value,
+                            Formality::IDENTIFIERS.join(", ")
+                        );
+                    };
+
+                    (code.to_owned(), formality)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Resolve language flags into target languages and their output paths, exiting if any language
+/// code is unknown or any path is unusable. Languages without a path use the default path, in the
+/// given format.
+fn resolve_language_flags(
+    backend: &dyn TranslationBackend,
+    flags: &[LanguageFlag],
+    format: LocaleFormat,
+) -> Vec<(Language, PathBuf)> {
+    flags
         .iter()
-        .for_each(|l| manifest_data.languages.push(l.clone()));
+        .map(|flag| {
+            let Some(language) = interact::find_language(backend.target_languages(), &flag.code)
+            else {
+                exit!("Unknown target language <r>'{}'</>.", flag.code);
+            };
 
-    if !interact::confirm_prompt(&cformat!(
-        "Are you sure you want to translate {}?",
-        match target_languages.len() {
-            1 => "this file",
-            _ => "these files",
+            let path = flag
+                .path
+                .clone()
+                .unwrap_or_else(|| interact::default_output_locale(&language, format));
+            if let Err(e) = interact::validate_output_locale(&path, format) {
+                exit!(
+                    "Invalid output path <r>'{}'</> for language <g>'{}'</>: {}",
+                    path.to_string_lossy(),
+                    language.code,
+                    e
+                );
+            }
+
+            (language, path)
+        })
+        .collect()
+}
+
+/// Prompt the user to set up the project, run initial translations, and write the app data to its
+/// directory.
+///
+/// Any values provided in `flags` are used instead of prompting for them.
+fn set_up_project(backend: &dyn TranslationBackend, flags: SetupFlags, concurrency: usize) {
+    let mut manifest_data = LocaleManifest::from_user_setup(
+        backend,
+        flags.source_language.as_deref(),
+        flags.source_path,
+        flags.assume_yes,
+    );
+
+    let target_locales = match flags.languages.is_empty() {
+        true => {
+            let target_languages = interact::select_target_languages(backend, None);
+            let mut output_paths = interact::select_output_locale_all(
+                &target_languages,
+                LocaleFormat::of(&manifest_data.source_locale_path),
+            );
+            target_languages
+                .into_iter()
+                .filter_map(|l| output_paths.remove(&l.code).map(|p| (l, p)))
+                .collect()
         }
-    )) {
+        false => resolve_language_flags(
+            backend,
+            &flags.languages,
+            LocaleFormat::of(&manifest_data.source_locale_path),
+        ),
+    };
+
+    if target_locales.is_empty() {
+        exit!("No target languages were selected.");
+    }
+
+    let mut target_languages = Vec::new();
+    for (lang, path) in target_locales {
+        manifest_data.locale_paths.insert(lang.code.clone(), path);
+        manifest_data.languages.push(lang.clone());
+        target_languages.push(lang);
+    }
+
+    if !flags.assume_yes
+        && !interact::confirm_prompt(&cformat!(
+            "Are you sure you want to translate {}?",
+            match target_languages.len() {
+                1 => "this file",
+                _ => "these files",
+            }
+        ))
+    {
         exit!("Translation canceled.");
     }
 
-    // let Some(source_document) = LocaleDocument::source(&manifest_data) else {
-    //     exit!(
-    //         "Missing source locale data. Ensure you are in the correct working directory and run 'ltranslate project setup' to install ltranslate into your project if necessary.",
-    //     );
-    // };
+    let Some(source_document) = LocaleDocument::source(&manifest_data) else {
+        exit!(
+            "Missing source locale data. Ensure that the source locale file still exists at the path you provided."
+        );
+    };
 
-    eprintln!("Translation in progress. Please wait...");
-    // let source_text = LocaleDocument::get_raw_text_data(&source_document);
-    for lang in target_languages {
-        // manifest_data.languages.push(lang.clone());
-        // LocaleDocument::translate_full(
-        //     deepl_context,
-        //     &manifest_data,
-        //     &source_document,
-        //     &source_text,
-        //     lang.clone(),
-        // )
-        // .write_out(None);
+    eprintln!(
+        "Translation in progress using {}. Please wait...",
+        backend.name()
+    );
+    let source_data = source_document.flat_data();
+    let source_text = LocaleDocument::get_raw_text_data(&source_data);
+    let results = run_concurrently(&target_languages, concurrency, |lang| {
+        ceprintln!("Translating locale <g>'{}'</>...", lang.code);
+        LocaleDocument::translate_full(
+            backend,
+            &manifest_data,
+            &source_document,
+            &source_text,
+            lang.clone(),
+        )
+    });
+
+    exit_if_cancelled("project setup");
+    let mut lockfile = Lockfile::default();
+    let mut failed_languages = Vec::new();
+    for (lang, result) in target_languages.into_iter().zip(results) {
+        let locale_document = match result {
+            Ok(locale_document) => locale_document,
+            Err(e) => {
+                ceprintln!("Failed to translate locale <r>'{}'</>: {}.", lang.code, e);
+                failed_languages.push(lang);
+                continue;
+            }
+        };
 
-        ceprintln!("Successfully translated locale <g>'{}'</>.", lang.code,);
+        lockfile.record(&lang.code, &source_data, &locale_document.flat_data());
+        locale_document.write_out(None);
+        ceprintln!("Successfully translated locale <g>'{}'</>.", lang.code);
     }
 
-    eprintln!("All translations complete! Writing app data...");
-    // AppData::new(manifest_data, source_document).write_out();
+    manifest_data.remove_languages(&failed_languages);
+    if manifest_data.languages.is_empty() {
+        exit!("No locales were translated successfully, so the project was not set up.");
+    }
+
+    eprintln!("Writing app data...");
+    let created_locales = manifest_data.locale_paths.clone();
+    AppData::new(manifest_data, lockfile).write_out();
+    journal::finish();
     eprintln!("App data written successfully.");
+    report_usage(backend);
+
+    eprintln!("Created the following locale files:");
+    for (code, path) in created_locales {
+        ceprintln!("  <g>{}</> -> {}", code, path.to_string_lossy());
+    }
+
+    if !failed_languages.is_empty() {
+        ceprintln!(
+            "<y>The following languages failed to translate and were not added to the project:</> {}",
+            failed_languages
+                .iter()
+                .map(|l| l.code.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        ceprintln!("You can add them later using <m>'ltranslate project manage'</>.");
+    }
 
     ceprintln!(
-        "<r,s>WARNING:</> Do not edit anything in the <g>'ltranslate'</> directory or the foreign locale files; doing so will corrupt your project. Use <m>'ltranslate project manage'</> to change project settings.",
+        "<r,s>WARNING:</> Do not edit anything in the <g>'ltranslate'</> directory; doing so will corrupt your project. Foreign locale files are safe to edit, and edited values are kept. Use <m>'ltranslate project manage'</> to change project settings.",
     );
 }
 
 /// Allow the user to change a project setting.
-fn manage_project(deepl_context: &DeepLContext) {
+///
+/// If any settings are provided in `flags`, they are all applied without prompting. Otherwise, the
+/// user is prompted to pick a setting to change. If `dry_run` is set, the changes are only
+/// described, and nothing is translated or written.
+fn manage_project(
+    backend: &dyn TranslationBackend,
+    flags: ManageFlags,
+    dry_run: bool,
+    concurrency: usize,
+) {
     let Some(mut manifest_data) = LocaleManifest::get_existing() else {
         exit!(
             "Missing project data. Ensure you are in the correct working directory and run 'ltranslate project setup' to install ltranslate into your project if necessary."
         );
     };
 
-    let target_setting = interact::select_project_setting();
-    match target_setting {
-        ProjectSetting::EditSourcePath => {
-            manifest_data.source_locale_path = interact::select_source_locale();
-            manifest_data.write_out();
-        }
-        ProjectSetting::EditLangugages => {
-            let (Some(source_document_history), Some(source_document_current)) = (
-                LocaleDocument::source_history(),
-                LocaleDocument::source(&manifest_data),
-            ) else {
-                exit!("Missing source locale or source locale history file.");
+    let Some(mut lockfile) = Lockfile::read(&manifest_data) else {
+        exit!("Missing lockfile <g>'{}'</>.", LOCKFILE_PATH);
+    };
+
+    if !flags.is_empty() {
+        if let Some(code) = &flags.source_language {
+            let Some(source_language) = interact::find_language(backend.source_languages(), code)
+            else {
+                exit!("Unknown source language <r>'{}'</>.", code);
             };
 
-            if LocaleDataDiff::diff(&source_document_history.data, &source_document_current.data)
-                .is_some()
-            {
+            set_source_language(&mut manifest_data, source_language, dry_run);
+        }
+
+        if let Some(path) = flags.source_path {
+            if !file_exists(&path) {
                 exit!(
-                    "Language list cannot be edited after changes have been made to the source locale file. Please update all translations using 'ltranslate project update' and try again."
+                    "Source locale file <r>'{}'</> does not exist.",
+                    path.to_string_lossy()
                 );
             }
 
-            let enabled_languages = &manifest_data.languages;
-            let selected_languages =
-                interact::select_target_languages(deepl_context, Some(enabled_languages));
-
-            let diff = LanguageDiff::diff(enabled_languages, &selected_languages);
-            if let Some(diff) = diff {
-                manifest_data.remove_languages(&diff.removed);
-                if !diff.removed.is_empty() {
-                    eprintln!(
-                        "It looks like you've removed one or more languages. Note that the files are not deleted automatically, so if you wish to delete them, remember to do so."
-                    );
+            set_source_path(&mut manifest_data, path, dry_run);
+        }
+
+        if !flags.added_languages.is_empty() || !flags.removed_languages.is_empty() {
+            for code in &flags.removed_languages {
+                if !manifest_data
+                    .languages
+                    .iter()
+                    .any(|l| l.code.eq_ignore_ascii_case(code))
+                {
+                    exit!("Language <r>'{}'</> is not enabled.", code);
                 }
+            }
 
-                let source_text = LocaleDocument::get_raw_text_data(&source_document_current);
-                for added_lang in diff.added {
-                    manifest_data.locale_paths.insert(
-                        added_lang.code.clone(),
-                        interact::select_output_locale(&added_lang),
-                    );
-                    manifest_data.languages.push(added_lang.clone());
-
-                    LocaleDocument::translate_full(
-                        deepl_context,
-                        &manifest_data,
-                        &source_document_current,
-                        &source_text,
-                        added_lang,
-                    )
-                    .write_out(None);
+            for flag in &flags.added_languages {
+                if manifest_data
+                    .languages
+                    .iter()
+                    .any(|l| l.code.eq_ignore_ascii_case(&flag.code))
+                {
+                    exit!("Language <r>'{}'</> is already enabled.", flag.code);
                 }
             }
 
-            manifest_data.write_out();
+            let added_locales = resolve_language_flags(
+                backend,
+                &flags.added_languages,
+                LocaleFormat::of(&manifest_data.source_locale_path),
+            );
+            let mut selected_languages = manifest_data
+                .languages
+                .iter()
+                .filter(|l| {
+                    !flags
+                        .removed_languages
+                        .iter()
+                        .any(|code| l.code.eq_ignore_ascii_case(code))
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            let mut output_paths = BTreeMap::new();
+            for (lang, path) in added_locales {
+                if !selected_languages.contains(&lang) {
+                    selected_languages.push(lang.clone());
+                }
+
+                output_paths.insert(lang.code, path);
+            }
+
+            edit_languages(
+                backend,
+                &mut manifest_data,
+                &mut lockfile,
+                selected_languages,
+                output_paths,
+                dry_run,
+                concurrency,
+            );
+        }
+
+        for (code, formality) in flags.formality {
+            let Some(language) = interact::find_language(&manifest_data.languages, &code) else {
+                exit!("Language <r>'{}'</> is not enabled.", code);
+            };
+
+            set_formality(
+                backend,
+                &mut manifest_data,
+                &mut lockfile,
+                &language,
+                formality,
+                dry_run,
+            );
+        }
+
+        if dry_run {
+            finish_dry_run(backend);
+            return;
+        }
+
+        manifest_data.write_out();
+        lockfile.write_out(None);
+        journal::finish();
+        eprintln!("Project settings updated.");
+        return;
+    }
+
+    let target_setting = interact::select_project_setting();
+    match target_setting {
+        ProjectSetting::SourcePath => {
+            let path = interact::select_source_locale(&manifest_data.source_language);
+            set_source_path(&mut manifest_data, path, dry_run);
+        }
+        ProjectSetting::SourceLanguage => {
+            let source_language = interact::select_source_language(backend);
+            set_source_language(&mut manifest_data, source_language, dry_run);
+        }
+        ProjectSetting::Languages => {
+            let selected_languages =
+                interact::select_target_languages(backend, Some(&manifest_data.languages));
+            edit_languages(
+                backend,
+                &mut manifest_data,
+                &mut lockfile,
+                selected_languages,
+                BTreeMap::new(),
+                dry_run,
+                concurrency,
+            );
+        }
+        ProjectSetting::Formality => {
+            let language = interact::select_enabled_language(&manifest_data.languages);
+            let formality =
+                interact::select_formality(&language, manifest_data.formality(&language));
+            set_formality(
+                backend,
+                &mut manifest_data,
+                &mut lockfile,
+                &language,
+                formality,
+                dry_run,
+            );
+        }
+    }
+
+    if dry_run {
+        finish_dry_run(backend);
+        return;
+    }
+
+    manifest_data.write_out();
+    lockfile.write_out(None);
+    journal::finish();
+}
+
+/// Change the source language of a project, exiting if it is currently a target language.
+fn set_source_language(
+    manifest_data: &mut LocaleManifest,
+    source_language: Language,
+    dry_run: bool,
+) {
+    if manifest_data
+        .languages
+        .iter()
+        .any(|l| l.code == source_language.code)
+    {
+        exit!(
+            "<g>'{}'</> is currently a target language. Remove it from the enabled languages before making it the source language.",
+            source_language.code
+        );
+    }
+
+    if dry_run {
+        ceprintln!(
+            "Source language would be changed to <g>'{}'</>.",
+            source_language.code
+        );
+    } else {
+        eprintln!(
+            "Source language updated. Existing translations are not retranslated automatically, so make sure the source locale path points to a file in the new language."
+        );
+    }
+
+    manifest_data.source_language = source_language;
+}
+
+/// Change the formality of a target language.
+///
+/// If the backend can apply the new formality, the existing translations of the language are
+/// forgotten, except for those edited by hand, so that the next update retranslates them.
+fn set_formality(
+    backend: &dyn TranslationBackend,
+    manifest_data: &mut LocaleManifest,
+    lockfile: &mut Lockfile,
+    language: &Language,
+    formality: Formality,
+    dry_run: bool,
+) {
+    if manifest_data.formality(language) == formality {
+        ceprintln!(
+            "Formality of <g>'{}'</> is already <g>'{}'</>.",
+            language.code,
+            formality
+        );
+        return;
+    }
+
+    if dry_run {
+        ceprintln!(
+            "Formality of <g>'{}'</> would be changed to <g>'{}'</>.",
+            language.code,
+            formality
+        );
+    }
+
+    manifest_data.set_formality(backend, language, formality);
+    if dry_run || !backend.supports_formality(&language.code) {
+        return;
+    }
+
+    lockfile.forget_unedited(&language.code);
+    ceprintln!(
+        "Formality of <g>'{}'</> changed to <g>'{}'</>. Run <m>'ltranslate project update'</> to retranslate its existing values.",
+        language.code,
+        formality
+    );
+}
+
+/// Change the path of the source locale file of a project.
+fn set_source_path(manifest_data: &mut LocaleManifest, path: PathBuf, dry_run: bool) {
+    for (code, locale_path) in &manifest_data.locale_paths {
+        if let Err(e) = interact::validate_output_format(locale_path, LocaleFormat::of(&path)) {
+            exit!(
+                "Cannot use <r>'{}'</> as the source locale, as locale <g>'{}'</> could no longer be updated: {}",
+                path.to_string_lossy(),
+                code,
+                e
+            );
         }
     }
+
+    if dry_run {
+        ceprintln!(
+            "Source locale path would be changed to <g>'{}'</>.",
+            path.to_string_lossy()
+        );
+    }
+
+    manifest_data.source_locale_path = path;
+}
+
+/// Replace the enabled languages of a project with `selected_languages`, translating any newly
+/// added languages.
+///
+/// Added languages are written to their path in `output_paths`, or the user is prompted for a path
+/// if it is missing. If `dry_run` is set, the planned changes are printed instead of being written.
+fn edit_languages(
+    backend: &dyn TranslationBackend,
+    manifest_data: &mut LocaleManifest,
+    lockfile: &mut Lockfile,
+    selected_languages: Vec<Language>,
+    mut output_paths: BTreeMap<String, PathBuf>,
+    dry_run: bool,
+    concurrency: usize,
+) {
+    let Some(source_document_current) = LocaleDocument::source(manifest_data) else {
+        exit!("Missing source locale file.");
+    };
+
+    let Some(diff) = LanguageDiff::diff(&manifest_data.languages, &selected_languages) else {
+        return;
+    };
+
+    manifest_data.remove_languages(&diff.removed);
+    for removed_lang in &diff.removed {
+        lockfile.remove_language(&removed_lang.code);
+    }
+
+    let source_data = source_document_current.flat_data();
+    if dry_run {
+        let source_keys = source_data.keys().cloned().collect::<Vec<_>>();
+        for removed_lang in &diff.removed {
+            print_planned_changes(&removed_lang.code, &[], &[], &[], &source_keys, 0);
+        }
+    } else if !diff.removed.is_empty() {
+        eprintln!(
+            "It looks like you've removed one or more languages. Note that the files are not deleted automatically, so if you wish to delete them, remember to do so."
+        );
+    }
+
+    for added_lang in &diff.added {
+        let output_path = output_paths.remove(&added_lang.code).unwrap_or_else(|| {
+            interact::select_output_locale(
+                added_lang,
+                LocaleFormat::of(&manifest_data.source_locale_path),
+            )
+        });
+        manifest_data
+            .locale_paths
+            .insert(added_lang.code.clone(), output_path);
+        manifest_data.languages.push(added_lang.clone());
+    }
+
+    // Dry runs translate one language at a time, so that the characters billed for each language
+    // can be told apart.
+    let concurrency = if dry_run { 1 } else { concurrency };
+    let source_text = LocaleDocument::get_raw_text_data(&source_data);
+    let results = run_concurrently(&diff.added, concurrency, |added_lang| {
+        ceprintln!("Translating locale <g>'{}'</>...", added_lang.code);
+        let characters_before = dry_run.then(|| billed_characters(backend));
+        LocaleDocument::translate_full(
+            backend,
+            manifest_data,
+            &source_document_current,
+            &source_text,
+            added_lang.clone(),
+        )
+        .map(|locale_document| {
+            let characters = characters_since(backend, characters_before);
+            (locale_document, characters)
+        })
+    });
+
+    exit_if_cancelled("project manage");
+    for (added_lang, result) in diff.added.into_iter().zip(results) {
+        let (locale_document, characters) = match result {
+            Ok(translated) => translated,
+            Err(e) => {
+                ceprintln!(
+                    "Failed to translate locale <r>'{}'</>, so it was not added: {}.",
+                    added_lang.code,
+                    e
+                );
+                manifest_data.remove_languages(std::slice::from_ref(&added_lang));
+                continue;
+            }
+        };
+
+        if dry_run {
+            print_planned_changes(
+                &added_lang.code,
+                &locale_document
+                    .flat_data()
+                    .keys()
+                    .cloned()
+                    .collect::<Vec<_>>(),
+                &[],
+                &[],
+                &[],
+                characters,
+            );
+            continue;
+        }
+
+        lockfile.record(&added_lang.code, &source_data, &locale_document.flat_data());
+        locale_document.write_out(None);
+        ceprintln!(
+            "Successfully translated locale <g>'{}'</>.",
+            added_lang.code
+        );
+    }
 }
 
 /// Update all foreign locale files based on any edits made to the source file.
-fn update_project(deepl_context: &DeepLContext) {
+///
+/// If `dry_run` is set, the keys which would be added, retranslated, or removed in each locale are
+/// printed instead, and nothing is written. `use_cache` is only used to tell the user where the
+/// translations which succeeded are kept if the update fails.
+fn update_project(
+    backend: &dyn TranslationBackend,
+    dry_run: bool,
+    use_cache: bool,
+    concurrency: usize,
+) {
     let Some(manifest_data) = LocaleManifest::get_existing() else {
         exit!(
             "Missing project data. Ensure you are in the correct working directory and run 'ltranslate project setup' to install ltranslate into your project if necessary."
         );
     };
 
-    let (Some(source_document_history), Some(source_document_current)) = (
-        LocaleDocument::source_history(),
-        LocaleDocument::source(&manifest_data),
-    ) else {
-        exit!("Missing source locale or source locale history file.");
+    let Some(mut lockfile) = Lockfile::read(&manifest_data) else {
+        exit!("Missing lockfile <g>'{}'</>.", LOCKFILE_PATH);
     };
 
-    let Some(diff) =
-        LocaleDataDiff::diff(&source_document_history.data, &source_document_current.data)
-    else {
-        return;
+    let Some(source_document_current) = LocaleDocument::source(&manifest_data) else {
+        exit!("Missing source locale file.");
     };
 
-    let enabled_languages = &manifest_data.languages;
-    for lang in enabled_languages {
+    // Each language is compared against the source values it was last translated from, so a
+    // language which was added later or fell behind is only given the changes it is missing. Values
+    // which were edited by hand are found first, so that they are kept.
+    let source_data = source_document_current.flat_data();
+    let mut found_edits = false;
+    let mut stale_languages = Vec::new();
+    for lang in &manifest_data.languages {
+        let Some(locale_document) = LocaleDocument::from_language(&manifest_data, lang.clone())
+        else {
+            exit!("Missing locale file for language '{}'.", lang.code);
+        };
+
+        let edited_keys = lockfile.detect_edits(&lang.code, &locale_document.flat_data());
+        if !edited_keys.is_empty() {
+            found_edits = true;
+            ceprintln!(
+                "Found <c>{}</> values edited by hand in locale <g>'{}'</>, which will be kept: {}",
+                edited_keys.len(),
+                lang.code,
+                edited_keys.join(", ")
+            );
+        }
+
+        if let Some(diff) = lockfile.diff(&lang.code, &source_data) {
+            stale_languages.push((lang.clone(), diff));
+        }
+    }
+
+    if stale_languages.is_empty() {
+        if dry_run {
+            eprintln!("The source locale has not changed, so there is nothing to translate.");
+        } else if found_edits {
+            lockfile.write_out(None);
+        }
+
+        return;
+    }
+
+    // Dry runs translate one language at a time, so that the characters billed for each language
+    // can be told apart.
+    let concurrency = if dry_run { 1 } else { concurrency };
+    let results = run_concurrently(&stale_languages, concurrency, |(lang, diff)| {
         let Some(mut locale_document) = LocaleDocument::from_language(&manifest_data, lang.clone())
         else {
             exit!("Missing locale file for language '{}'.", lang.code);
         };
 
-        locale_document.update_translations(deepl_context, &diff);
-        locale_document.write_out(None);
+        ceprintln!("Translating locale <g>'{}'</>...", lang.code);
+        let characters_before = dry_run.then(|| billed_characters(backend));
+        locale_document
+            .update_translations(backend, &manifest_data, &source_document_current, diff)
+            .map(|translated_data| {
+                let characters = characters_since(backend, characters_before);
+                (locale_document, translated_data, characters)
+            })
+    });
+
+    exit_if_cancelled("project update");
+    let mut failed_languages = Vec::new();
+    let mut updated_documents = Vec::new();
+    let mut needs_review = false;
+    for ((lang, diff), result) in stale_languages.iter().zip(results) {
+        let (locale_document, translated_data, characters) = match result {
+            Ok(updated) => updated,
+            Err(e) => {
+                ceprintln!("Failed to translate locale <r>'{}'</>: {}.", lang.code, e);
+                failed_languages.push(lang.code.as_str());
+                continue;
+            }
+        };
+
+        if dry_run {
+            let (retranslated_keys, added_keys): (Vec<_>, Vec<_>) = diff
+                .changed_or_added
+                .keys()
+                .cloned()
+                .partition(|k| lockfile.is_locked(&lang.code, k));
+            print_planned_changes(
+                &lang.code,
+                &added_keys,
+                &retranslated_keys,
+                &format_renames(&diff.renamed),
+                &diff.removed,
+                characters,
+            );
+            check::print_keys("to review", &diff.review);
+            continue;
+        }
+
+        if !diff.renamed.is_empty() {
+            ceprintln!(
+                "Carried over <c>{}</> translations to renamed keys in locale <g>'{}'</>: {}",
+                diff.renamed.len(),
+                lang.code,
+                format_renames(&diff.renamed).join(", ")
+            );
+        }
+
+        if !diff.review.is_empty() {
+            needs_review = true;
+            ceprintln!(
+                "<y>Kept</> <c>{}</> values edited by hand in locale <g>'{}'</> whose source text has changed: {}",
+                diff.review.len(),
+                lang.code,
+                diff.review.join(", ")
+            );
+        }
+
+        lockfile.apply(&lang.code, &source_data, diff, &translated_data);
+        updated_documents.push(locale_document);
+    }
+
+    if dry_run {
+        finish_dry_run(backend);
+        return;
+    }
+
+    // Nothing is written unless every locale was updated, so that a failed update never leaves the
+    // project partly updated.
+    if !failed_languages.is_empty() {
+        report_usage(backend);
+        ceprintln!(
+            "The following locales failed to update: {}. No files were changed.",
+            failed_languages.join(", ")
+        );
+        if use_cache {
+            exit!(
+                "Translations which succeeded are kept in the translation memory, so they will not be billed again when you rerun <m>'ltranslate project update'</>."
+            );
+        } else {
+            exit!(
+                "Translations which succeeded are kept in the journal, so they will not be billed again if you choose to resume when you rerun <m>'ltranslate project update'</>."
+            );
+        }
+    }
+
+    let mut transaction = Transaction::new();
+    for locale_document in updated_documents {
+        transaction.stage(locale_document);
+    }
+
+    transaction.stage_lockfile(&lockfile);
+    transaction.commit();
+    lockfile::remove_source_history();
+    journal::finish();
+    report_usage(backend);
+
+    if needs_review {
+        ceprintln!(
+            "Some edited values need to be reviewed. Run <m>'ltranslate project review'</> to keep or retranslate them."
+        );
+    }
+}
+
+/// Check that every foreign locale is up to date with the source locale, exiting with a non-zero
+/// status if any are not.
+///
+/// A summary is always printed to stderr, and a JSON report is also printed to stdout if `json` is
+/// set.
+fn check_project(json: bool) {
+    let Some(manifest_data) = LocaleManifest::get_existing() else {
+        exit!(
+            "Missing project data. Ensure you are in the correct working directory and run 'ltranslate project setup' to install ltranslate into your project if necessary."
+        );
+    };
+
+    let report = CheckReport::new(&manifest_data);
+    report.print_summary();
+    if json {
+        report.print_json();
+    }
+
+    if !report.up_to_date {
+        std::process::exit(1);
+    }
+}
+
+/// List the values which were edited by hand and whose source text has changed since, for every
+/// language in `language_codes`, or every enabled language if it is empty.
+///
+/// If `accept` is set, the edited values are kept and no longer need review. If `retranslate` is
+/// set, they are discarded, so that the next update replaces them with new translations.
+fn review_project(language_codes: &[String], accept: bool, retranslate: bool) {
+    let Some(manifest_data) = LocaleManifest::get_existing() else {
+        exit!(
+            "Missing project data. Ensure you are in the correct working directory and run 'ltranslate project setup' to install ltranslate into your project if necessary."
+        );
+    };
+
+    let Some(mut lockfile) = Lockfile::read(&manifest_data) else {
+        exit!("Missing lockfile <g>'{}'</>.", LOCKFILE_PATH);
+    };
+
+    for code in language_codes {
+        if !manifest_data
+            .languages
+            .iter()
+            .any(|l| l.code.eq_ignore_ascii_case(code))
+        {
+            exit!("Language <r>'{}'</> is not enabled.", code);
+        }
+    }
+
+    // The context of each value is shown, as it may help to decide whether the edit still fits.
+    let source_metadata = LocaleDocument::source(&manifest_data)
+        .map(|d| metadata::collect_project(&d.data))
+        .unwrap_or_default();
+
+    let mut review_count = 0;
+    for lang in &manifest_data.languages {
+        if !language_codes.is_empty()
+            && !language_codes
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(&lang.code))
+        {
+            continue;
+        }
+
+        // Values which were edited again since they were flagged no longer need review.
+        if let Some(locale_document) = LocaleDocument::from_language(&manifest_data, lang.clone()) {
+            lockfile.detect_edits(&lang.code, &locale_document.flat_data());
+        }
+
+        let review_keys = lockfile.review_keys(&lang.code);
+        if review_keys.is_empty() {
+            continue;
+        }
+
+        review_count += review_keys.len();
+        ceprintln!("Locale <g>'{}'</>:", lang.code);
+        check::print_keys("to review", &review_keys);
+        for key in &review_keys {
+            if let Some(context) = source_metadata.get(key).and_then(|m| m.context.as_ref()) {
+                ceprintln!("    <g>'{}'</> context: {}", key, context);
+            }
+        }
+
+        if accept {
+            lockfile.accept_review(&lang.code, &review_keys);
+        } else if retranslate {
+            lockfile.forget(&lang.code, &review_keys);
+        }
+    }
+
+    if review_count == 0 {
+        eprintln!("No values need to be reviewed.");
+        return;
+    }
+
+    if accept {
+        lockfile.write_out(None);
+        eprintln!(
+            "Kept the edited values. They will be flagged again if their source text changes."
+        );
+    } else if retranslate {
+        lockfile.write_out(None);
+        ceprintln!(
+            "Discarded the edited values. Run <m>'ltranslate project update'</> to retranslate them."
+        );
+    } else {
+        ceprintln!(
+            "Check these values against the new source text, then run with <m>--accept</> to keep them or <m>--retranslate</> to replace them with new translations."
+        );
+    }
+}
+
+/// Remove unneeded entries from the project's translation memory.
+fn prune_cache() {
+    let Some(manifest_data) = LocaleManifest::get_existing() else {
+        exit!(
+            "Missing project data. Ensure you are in the correct working directory and run 'ltranslate project setup' to install ltranslate into your project if necessary."
+        );
+    };
+
+    let removed_count = backend::cache::prune(&manifest_data);
+    ceprintln!(
+        "Removed <c>{}</> entries from the translation memory.",
+        removed_count
+    );
+}
+
+/// Find the enabled language whose glossary `language_code` refers to, exiting if there is none.
+///
+/// Regional variants share a glossary, so any variant of an enabled language is accepted.
+fn find_glossary_language<'a>(
+    manifest_data: &'a LocaleManifest,
+    language_code: &str,
+) -> &'a Language {
+    let Some(language) = manifest_data.languages.iter().find(|l| {
+        glossary::glossary_language(&l.code) == glossary::glossary_language(language_code)
+    }) else {
+        exit!("Language <r>'{}'</> is not enabled.", language_code);
+    };
+
+    language
+}
+
+/// Set the translation of a term in the glossary of a target language, or remove the term if
+/// `target_term` is not given.
+fn edit_glossary(language_code: &str, source_term: &str, target_term: Option<&str>) {
+    let Some(manifest_data) = LocaleManifest::get_existing() else {
+        exit!(
+            "Missing project data. Ensure you are in the correct working directory and run 'ltranslate project setup' to install ltranslate into your project if necessary."
+        );
+    };
+
+    let language = find_glossary_language(&manifest_data, language_code);
+    let mut glossary = Glossary::read(&manifest_data.source_language.code, &language.code);
+    match target_term {
+        Some(target_term) => {
+            glossary.set(source_term, target_term);
+            glossary.write_out();
+            ceprintln!(
+                "Added <g>'{}'</> to glossary <g>'{}'</>.",
+                source_term.trim(),
+                glossary.name()
+            );
+        }
+        None => {
+            if !glossary.remove(source_term) {
+                exit!(
+                    "Term <r>'{}'</> is not in glossary <g>'{}'</>.",
+                    source_term,
+                    glossary.name()
+                );
+            }
+
+            glossary.write_out();
+            ceprintln!(
+                "Removed <g>'{}'</> from glossary <g>'{}'</>.",
+                source_term.trim(),
+                glossary.name()
+            );
+        }
+    }
+
+    ceprintln!("Run <m>'ltranslate glossary sync'</> to apply the change to later translations.");
+}
+
+/// Print the terms in every glossary of the project, or only that of `language_code` if given,
+/// along with whether each glossary has changed since it was last synced.
+fn list_glossaries(backend_kind: BackendKind, language_code: Option<&str>) {
+    let Some(manifest_data) = LocaleManifest::get_existing() else {
+        exit!(
+            "Missing project data. Ensure you are in the correct working directory and run 'ltranslate project setup' to install ltranslate into your project if necessary."
+        );
+    };
+
+    let glossaries = match language_code {
+        Some(code) => {
+            let language = find_glossary_language(&manifest_data, code);
+            vec![Glossary::read(
+                &manifest_data.source_language.code,
+                &language.code,
+            )]
+        }
+        None => Glossary::read_all(&manifest_data.source_language.code),
+    };
+
+    let glossaries = glossaries
+        .into_iter()
+        .filter(|g| !g.entries().is_empty())
+        .collect::<Vec<_>>();
+    if glossaries.is_empty() {
+        ceprintln!(
+            "No glossaries found. Use <m>'ltranslate glossary add'</> to add terms to a glossary."
+        );
+        return;
+    }
+
+    for glossary in glossaries {
+        let status = match manifest_data.glossaries.get(&glossary.name()) {
+            Some(synced) if synced.backend != backend_kind.identifier() => {
+                cformat!("<y>not synced with {}</>", backend_kind.identifier())
+            }
+            Some(synced) if synced.entries_hash == glossary.entries_hash() => {
+                cformat!("<g>synced</>")
+            }
+            Some(_) => cformat!("<y>changed since last sync</>"),
+            None => cformat!("<y>not synced</>"),
+        };
+
+        ceprintln!(
+            "Glossary <g>'{}'</> ({}, {}):",
+            glossary.name(),
+            glossary.path().to_string_lossy(),
+            status
+        );
+        for (source_term, target_term) in glossary.entries() {
+            println!("{source_term}\t{target_term}");
+        }
+    }
+}
+
+/// Upload every glossary file which has changed since it was last synced, replacing the glossary it
+/// was synced to before, and delete the glossaries of files which are now empty or were removed.
+///
+/// The ID of each synced glossary is stored in the manifest along with the backend it was created
+/// with, so that it is applied to later translations into its target language by that backend.
+/// Glossaries which were synced with a different backend are always synced again.
+fn sync_glossaries(backend: &dyn TranslationBackend) {
+    let Some(mut manifest_data) = LocaleManifest::get_existing() else {
+        exit!(
+            "Missing project data. Ensure you are in the correct working directory and run 'ltranslate project setup' to install ltranslate into your project if necessary."
+        );
+    };
+
+    let mut previous = std::mem::take(&mut manifest_data.glossaries);
+    let mut failed = false;
+    for glossary in Glossary::read_all(&manifest_data.source_language.code) {
+        let name = glossary.name();
+        let entries_hash = glossary.entries_hash();
+        let existing = previous.remove(&name);
+        if glossary.entries().is_empty() {
+            if let Some(existing) = existing {
+                previous.insert(name, existing);
+            }
+
+            continue;
+        }
+
+        if let Some(existing) = &existing
+            && existing.backend == backend.identifier()
+            && existing.entries_hash == entries_hash
+        {
+            ceprintln!("Glossary <g>'{}'</> is up to date.", name);
+            manifest_data.glossaries.insert(name, existing.clone());
+            continue;
+        }
+
+        match backend.create_glossary(
+            &format!("ltranslate {name}"),
+            &glossary.source_language,
+            &glossary.target_language,
+            glossary.entries(),
+        ) {
+            Ok(id) => {
+                ceprintln!(
+                    "Synced glossary <g>'{}'</> with <c>{}</> terms.",
+                    name,
+                    glossary.entries().len()
+                );
+                manifest_data.glossaries.insert(
+                    name,
+                    SyncedGlossary {
+                        backend: backend.identifier().to_owned(),
+                        id,
+                        entries_hash,
+                    },
+                );
+                if let Some(existing) = existing {
+                    delete_glossary(backend, &existing);
+                }
+            }
+            Err(e) => {
+                ceprintln!("Failed to sync glossary <r>'{}'</>: {}.", name, e);
+                failed = true;
+                if let Some(existing) = existing {
+                    manifest_data.glossaries.insert(name, existing);
+                }
+            }
+        }
     }
 
-    AppData::new(manifest_data, source_document_current).write_out();
+    // Whatever is left belongs to glossary files which are empty or no longer exist.
+    for (name, existing) in previous {
+        delete_glossary(backend, &existing);
+        ceprintln!("Removed glossary <g>'{}'</>.", name);
+    }
+
+    manifest_data.write_out();
+    if failed {
+        exit!(
+            "Some glossaries could not be synced. Translations will use their previous version, if there is one."
+        );
+    }
+}
+
+/// Delete a glossary which has been replaced or removed, warning instead of exiting if that fails,
+/// as the glossary is no longer used either way.
+///
+/// Glossaries created with a different backend are left alone, as they cannot be deleted with this
+/// one.
+fn delete_glossary(backend: &dyn TranslationBackend, glossary: &SyncedGlossary) {
+    if glossary.backend != backend.identifier() {
+        return;
+    }
+
+    if let Err(e) = backend.delete_glossary(&glossary.id) {
+        ceprintln!(
+            "<y>Failed to delete unused glossary</> <g>'{}'</>: {}.",
+            glossary.id,
+            e
+        );
+    }
 }
 
 /// Translate a single specified locale and write the translation to an output file.
 ///
-/// This function can be provided with a `target_language` value to avoid opening the language
-/// selector prompt.
+/// This function can be provided with a target language in `flags` to avoid opening the language
+/// selector prompt. The source language defaults to English unless one is provided, in which case
+/// the source language selector is opened if the code is invalid. If `dry_run` is set, the keys
+/// which would be translated are printed instead, and the output file is not written.
 fn translate_interactive(
-    deepl_context: &DeepLContext,
+    backend: &dyn TranslationBackend,
     input_file: PathBuf,
     output_file: PathBuf,
-    target_language: Option<String>,
+    flags: TranslateFlags,
+    dry_run: bool,
 ) {
-    let target_language = match target_language {
-        Some(language_code) => deepl_context
-            .available_target_langs
-            .iter()
-            .find(|l| l.code == language_code)
-            .cloned()
-            .unwrap_or_else(|| interact::select_target_language(deepl_context)),
-        None => interact::select_target_language(deepl_context),
+    let source_language = match flags.source_language {
+        Some(language_code) => interact::resolve_source_language(backend, &language_code),
+        None => Language::english(),
+    };
+
+    let target_language = match flags.target_language {
+        Some(language_code) => interact::resolve_target_language(backend, &language_code),
+        None => interact::select_target_language(backend),
     };
 
-    if !interact::confirm_prompt("Are you sure you want to translate this file?") {
+    if let Err(e) = interact::validate_output_format(&output_file, LocaleFormat::of(&input_file)) {
+        exit!(
+            "Invalid output path <r>'{}'</>: {}",
+            output_file.to_string_lossy(),
+            e
+        );
+    }
+
+    if !flags.assume_yes
+        && !dry_run
+        && !interact::confirm_prompt("Are you sure you want to translate this file?")
+    {
         exit!("Translation canceled.");
     }
 
-    let Some(source_data) = LocaleDocument::parse_data_from_file(&input_file) else {
+    let Some(source_document) = LocaleDocument::read(input_file, source_language, true) else {
         exit!("Missing input file. This is likely a logic bug.");
     };
 
-    LocaleDocument::translate_full_direct(
-        deepl_context,
-        &source_data,
+    let target_language_code = target_language.code.clone();
+    let locale_document = LocaleDocument::translate_full_direct(
+        backend,
+        &source_document,
         target_language,
         output_file,
+        flags.formality,
     )
-    .write_out(None);
+    .unwrap_or_else(|e| exit!("Failed to translate values: {}.", e));
+
+    if dry_run {
+        print_planned_changes(
+            &target_language_code,
+            &locale_document
+                .flat_data()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>(),
+            &[],
+            &[],
+            &[],
+            billed_characters(backend),
+        );
+        finish_dry_run(backend);
+        return;
+    }
+
+    locale_document.write_out(None);
 
     eprintln!("Translation complete. Output has been written to file.");
+    report_usage(backend);
+}
+
+/// Print the keys which a dry run found would change in a locale, and the number of characters
+/// which would be billed for it.
+fn print_planned_changes(
+    language_code: &str,
+    added_keys: &[String],
+    retranslated_keys: &[String],
+    renamed_keys: &[String],
+    removed_keys: &[String],
+    characters: u64,
+) {
+    ceprintln!(
+        "Planned changes for locale <g>'{}'</> (<c>{}</> characters):",
+        language_code,
+        characters
+    );
+    if added_keys.is_empty()
+        && retranslated_keys.is_empty()
+        && renamed_keys.is_empty()
+        && removed_keys.is_empty()
+    {
+        eprintln!("  no changes");
+    }
+
+    check::print_keys("to add", added_keys);
+    check::print_keys("to retranslate", retranslated_keys);
+    check::print_keys("to rename", renamed_keys);
+    check::print_keys("to remove", removed_keys);
+}
+
+/// Format renamed keys as `old -> new` for printing.
+fn format_renames(renamed: &[(String, String)]) -> Vec<String> {
+    renamed
+        .iter()
+        .map(|(old_key, new_key)| format!("{old_key} -> {new_key}"))
+        .collect()
+}
+
+/// Print the total number of characters a dry run would have been billed for.
+fn finish_dry_run(backend: &dyn TranslationBackend) {
+    ceprintln!(
+        "Dry run complete. <c>{}</> characters would be billed in total. No translation requests were made, and no files were written.",
+        billed_characters(backend)
+    );
+}
+
+/// Get the number of characters billed by the backend so far, or zero if it cannot be fetched.
+fn billed_characters(backend: &dyn TranslationBackend) -> u64 {
+    backend.usage().map(|u| u.character_count).unwrap_or(0)
+}
+
+/// Get the number of characters billed by the backend since an earlier reading, or zero if there
+/// was no earlier reading because this is not a dry run.
+fn characters_since(backend: &dyn TranslationBackend, characters_before: Option<u64>) -> u64 {
+    characters_before.map_or(0, |before| {
+        billed_characters(backend).saturating_sub(before)
+    })
+}
+
+/// Print the amount of translation credit used so far in the current billing period.
+fn report_usage(backend: &dyn TranslationBackend) {
+    match backend.usage() {
+        Ok(usage) if usage.character_limit == u64::MAX => ceprintln!(
+            "<c>{}</> characters translated using {}.",
+            usage.character_count,
+            backend.name()
+        ),
+        Ok(usage) => ceprintln!(
+            "<c>{}</> of <c>{}</> characters used in the current {} billing period.",
+            usage.character_count,
+            usage.character_limit,
+            backend.name()
+        ),
+        Err(e) => ceprintln!("<y>Could not fetch usage information:</> {}.", e),
+    }
 }
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value as JsonValue;
+
+use crate::types::LocaleData;
+use crate::{CONTEXT_PATH, exit, key_path};
+
+/// Notes about the values of a source locale, keyed by key path, which help the translation
+/// service to translate them.
+pub type FlatMetadata = BTreeMap<String, KeyMetadata>;
+
+/// Notes about a single source value.
+#[derive(Clone, Default)]
+pub struct KeyMetadata {
+    /// A description of where or how the value is used, such as "Verb, button which opens a file",
+    /// which is sent along with the value but never translated itself.
+    pub context: Option<String>,
+    /// Set when the value is marked with `"translate": false`, in which case it is copied to every
+    /// target locale as it is.
+    pub verbatim: bool,
+}
+
+/// The fields of a single metadata key, before any are inherited from the objects around it.
+struct OwnMetadata {
+    context: Option<String>,
+    translate: Option<bool>,
+}
+
+/// Collect the metadata of every value in a source locale.
+///
+/// Metadata is written in a sibling key named after the key it describes with an `@` prefix, as in
+/// ARB files. It is either a string, which is used as the context, or an object with a `context`
+/// or `description` field, and an optional `translate` field which is set to `false` to keep the
+/// value from being translated. Metadata attached to an object applies to every value inside it,
+/// unless a value has metadata of its own.
+pub fn collect(data: &LocaleData) -> FlatMetadata {
+    let mut metadata = FlatMetadata::new();
+    collect_object(None, data, &KeyMetadata::default(), &mut metadata);
+    metadata
+}
+
+/// Collect the metadata of every value in the source locale of a project, including the context
+/// listed for each key path in [`CONTEXT_PATH`].
+///
+/// Context written in the source locale itself takes priority. A key path in the context file
+/// also applies to every value below it.
+pub fn collect_project(data: &LocaleData) -> FlatMetadata {
+    let mut metadata = collect(data);
+    let contexts = read_context_file();
+    if contexts.is_empty() {
+        return metadata;
+    }
+
+    for key in key_path::flatten(data).keys() {
+        let entry = metadata.entry(key.clone()).or_default();
+        if entry.context.is_some() {
+            continue;
+        }
+
+        // The longest matching key path is the most specific one.
+        entry.context = contexts
+            .iter()
+            .filter(|(path, _)| *path == key || key.starts_with(&format!("{path}.")))
+            .max_by_key(|(path, _)| path.len())
+            .map(|(_, context)| context.clone());
+    }
+
+    metadata.retain(|_, m| m.context.is_some() || m.verbatim);
+    metadata
+}
+
+fn collect_object(
+    path: Option<&str>,
+    object: &LocaleData,
+    inherited: &KeyMetadata,
+    metadata: &mut FlatMetadata,
+) {
+    for (key, value) in object
+        .iter()
+        .filter(|(k, _)| !key_path::is_metadata_key(k, object))
+    {
+        let child_path = match path {
+            Some(path) => key_path::join(path, key),
+            None => key_path::escape_segment(key),
+        };
+
+        let own = object.get(&format!("@{key}")).map(parse_metadata);
+        let child_metadata = match own {
+            Some(own) => KeyMetadata {
+                context: own.context.or_else(|| inherited.context.clone()),
+                verbatim: own.translate.map_or(inherited.verbatim, |t| !t),
+            },
+            None => inherited.clone(),
+        };
+
+        collect_value(&child_path, value, &child_metadata, metadata);
+    }
+}
+
+fn collect_value(
+    path: &str,
+    value: &JsonValue,
+    inherited: &KeyMetadata,
+    metadata: &mut FlatMetadata,
+) {
+    match value {
+        JsonValue::String(_) if inherited.context.is_some() || inherited.verbatim => {
+            metadata.insert(path.to_owned(), inherited.clone());
+        }
+        JsonValue::Object(object) => collect_object(Some(path), object, inherited, metadata),
+        JsonValue::Array(array) => {
+            for (index, child) in array.iter().enumerate() {
+                collect_value(
+                    &key_path::join(path, &index.to_string()),
+                    child,
+                    inherited,
+                    metadata,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Parse the value of a metadata key.
+fn parse_metadata(value: &JsonValue) -> OwnMetadata {
+    let (context, translate) = match value {
+        JsonValue::String(context) => (Some(context.clone()), None),
+        JsonValue::Object(fields) => (
+            fields
+                .get("context")
+                .or_else(|| fields.get("description"))
+                .and_then(|c| c.as_str())
+                .map(str::to_owned),
+            fields.get("translate").and_then(|t| t.as_bool()),
+        ),
+        _ => (None, None),
+    };
+
+    OwnMetadata {
+        context: context.filter(|c| !c.trim().is_empty()),
+        translate,
+    }
+}
+
+/// Read the context file of the project, if there is one.
+fn read_context_file() -> BTreeMap<String, String> {
+    let Ok(contexts) = std::fs::read_to_string(CONTEXT_PATH) else {
+        return BTreeMap::new();
+    };
+
+    let Ok(contexts) = serde_json::from_str(&contexts) else {
+        exit!(
+            "Failed to parse context file <g>'{}'</>. It must be a JSON object mapping key paths to strings.",
+            CONTEXT_PATH
+        );
+    };
+
+    contexts
+}
@@ -0,0 +1,458 @@
+use std::fmt::{Display, Formatter};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::exit;
+
+/// Matches the tokens which stand in for placeholders while a value is being translated. Some
+/// whitespace is allowed inside the braces, as translation engines occasionally insert it.
+const TOKEN_PATTERN: &str = r"\{\s*PH_(\d+)\s*\}";
+
+/// A placeholder or interpolation syntax which must not be altered by translation.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlaceholderSyntax {
+    /// Single-brace placeholders such as `{name}` or `{0}`.
+    Brace,
+    /// Double-brace placeholders such as `{{count}}` or `{{- name}}`.
+    DoubleBrace,
+    /// `printf`-style format specifiers such as `%s`, `%d`, or `%1$d`. The space flag is not
+    /// supported, since text such as "50% off" would otherwise contain a placeholder.
+    Printf,
+    /// Colon-prefixed placeholders such as `:attr`, as used by Laravel.
+    Colon,
+    /// i18next nesting such as `$t(key)`.
+    I18nextNesting,
+}
+
+/// The placeholder settings for a project, as stored in the manifest.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlaceholderConfig {
+    #[serde(default)]
+    pub syntaxes: Vec<PlaceholderSyntax>,
+    /// Additional regular expressions matching project-specific placeholders.
+    #[serde(default)]
+    pub custom: Vec<String>,
+}
+
+/// Finds placeholders in values, shields them from translation, and checks that the translated
+/// values still contain the same placeholders.
+pub struct PlaceholderMatcher {
+    pattern: Regex,
+    token_pattern: Regex,
+}
+
+/// A value whose placeholders have been replaced by numbered tokens.
+pub struct ShieldedText {
+    pub text: String,
+    placeholders: Vec<ShieldedPlaceholder>,
+}
+
+struct ShieldedPlaceholder {
+    text: String,
+    /// Whether the placeholder was found in literal text by a [`PlaceholderSyntax`], as opposed to
+    /// being passed in as a [`TextPart::Placeholder`].
+    literal: bool,
+}
+
+/// A piece of text which is either translatable, or a placeholder which must be kept verbatim.
+pub enum TextPart {
+    Literal(String),
+    Placeholder(String),
+}
+
+pub enum PlaceholderError {
+    /// The translation is missing one or more placeholders from the source value.
+    Missing(Vec<String>),
+    /// The translation contains one or more placeholders which are not in the source value.
+    Unexpected(Vec<String>),
+}
+
+impl PlaceholderSyntax {
+    fn pattern(self) -> &'static str {
+        match self {
+            PlaceholderSyntax::Brace => r"\{[A-Za-z0-9_.$-]+\}",
+            PlaceholderSyntax::DoubleBrace => r"\{\{[^{}]+\}\}",
+            PlaceholderSyntax::Printf => r"%(?:\d+\$)?[-+0#]*\d*(?:\.\d+)?[sdifuxXoeEgGcp@%]",
+            PlaceholderSyntax::Colon => r"\B:[A-Za-z_][A-Za-z0-9_]*",
+            PlaceholderSyntax::I18nextNesting => r"\$t\([^()]*\)",
+        }
+    }
+}
+
+impl Default for PlaceholderConfig {
+    fn default() -> Self {
+        PlaceholderConfig {
+            syntaxes: vec![
+                PlaceholderSyntax::DoubleBrace,
+                PlaceholderSyntax::Brace,
+                PlaceholderSyntax::Printf,
+                PlaceholderSyntax::I18nextNesting,
+            ],
+            custom: Vec::new(),
+        }
+    }
+}
+
+impl PlaceholderMatcher {
+    /// Compile the patterns for a given [`PlaceholderConfig`], exiting if any custom pattern is not
+    /// a valid regular expression.
+    pub fn new(config: &PlaceholderConfig) -> Self {
+        for custom_pattern in &config.custom {
+            if Regex::new(custom_pattern).is_err() {
+                exit!(
+                    "Custom placeholder pattern <r>'{}'</> is not a valid regular expression.",
+                    custom_pattern
+                );
+            }
+        }
+
+        // Longer syntaxes are matched first so that, for example, `{{count}}` is not mistaken for
+        // `{count}` surrounded by braces. Text which looks like a token is always shielded too, so
+        // that it cannot be mistaken for a real token when the translation is restored.
+        let mut syntaxes = config.syntaxes.clone();
+        syntaxes.sort_by_key(|s| *s != PlaceholderSyntax::DoubleBrace);
+        let alternatives = std::iter::once(TOKEN_PATTERN)
+            .chain(config.custom.iter().map(String::as_str))
+            .chain(syntaxes.iter().map(|s| s.pattern()))
+            .map(|p| format!("(?:{p})"))
+            .collect::<Vec<_>>();
+
+        let pattern = Regex::new(&alternatives.join("|"))
+            .unwrap_or_else(|_| exit!("Failed to compile placeholder patterns."));
+
+        PlaceholderMatcher {
+            pattern,
+            token_pattern: Regex::new(TOKEN_PATTERN)
+                .unwrap_or_else(|_| exit!("Failed to compile placeholder token pattern.")),
+        }
+    }
+
+    /// Replace every placeholder in a value with a numbered token which translation engines will
+    /// leave alone.
+    pub fn shield(&self, text: &str) -> ShieldedText {
+        self.shield_parts(&[TextPart::Literal(text.to_owned())])
+    }
+
+    /// Shield a value which has already been split into parts. Every [`TextPart::Placeholder`] is
+    /// replaced by a token, as is every placeholder found within a [`TextPart::Literal`].
+    pub fn shield_parts(&self, parts: &[TextPart]) -> ShieldedText {
+        let mut placeholders = Vec::new();
+        let mut text = String::new();
+        for part in parts {
+            match part {
+                TextPart::Literal(literal) => {
+                    let mut last_end = 0;
+                    for found in self.find_all(literal) {
+                        text.push_str(&literal[last_end..found.start()]);
+                        text.push_str(&Self::token(placeholders.len()));
+                        placeholders.push(ShieldedPlaceholder {
+                            text: found.as_str().to_owned(),
+                            literal: true,
+                        });
+                        last_end = found.end();
+                    }
+
+                    text.push_str(&literal[last_end..]);
+                }
+                TextPart::Placeholder(placeholder) => {
+                    text.push_str(&Self::token(placeholders.len()));
+                    placeholders.push(ShieldedPlaceholder {
+                        text: placeholder.clone(),
+                        literal: false,
+                    });
+                }
+            }
+        }
+
+        ShieldedText { text, placeholders }
+    }
+
+    /// Restore the placeholders in a translated value, and check that it contains exactly the same
+    /// placeholders as the source value.
+    pub fn restore(
+        &self,
+        shielded: &ShieldedText,
+        translated: &str,
+    ) -> Result<String, PlaceholderError> {
+        Ok(self
+            .restore_parts(shielded, translated)?
+            .into_iter()
+            .map(|part| match part {
+                TextPart::Literal(text) | TextPart::Placeholder(text) => text,
+            })
+            .collect())
+    }
+
+    /// Restore the placeholders in a translated value as in [`PlaceholderMatcher::restore()`], but
+    /// keep any [`TextPart::Placeholder`] passed to [`PlaceholderMatcher::shield_parts()`]
+    /// separate from the surrounding text.
+    pub fn restore_parts(
+        &self,
+        shielded: &ShieldedText,
+        translated: &str,
+    ) -> Result<Vec<TextPart>, PlaceholderError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut unexpected = Vec::new();
+        let mut restored_count = vec![0; shielded.placeholders.len()];
+        let mut last_end = 0;
+        for captures in self.token_pattern.captures_iter(translated) {
+            let token = &captures[0];
+            let token_range = captures.get(0).map_or(0..0, |m| m.range());
+            literal.push_str(&translated[last_end..token_range.start]);
+            last_end = token_range.end;
+
+            let Some(index) = captures[1]
+                .parse::<usize>()
+                .ok()
+                .filter(|i| *i < shielded.placeholders.len())
+            else {
+                unexpected.push(token.to_owned());
+                continue;
+            };
+
+            restored_count[index] += 1;
+            let placeholder = &shielded.placeholders[index];
+            if placeholder.literal {
+                literal.push_str(&placeholder.text);
+            } else {
+                parts.push(TextPart::Literal(std::mem::take(&mut literal)));
+                parts.push(TextPart::Placeholder(placeholder.text.clone()));
+            }
+        }
+
+        literal.push_str(&translated[last_end..]);
+        parts.push(TextPart::Literal(literal));
+
+        let missing = shielded
+            .placeholders
+            .iter()
+            .zip(&restored_count)
+            .filter(|(_, count)| **count == 0)
+            .map(|(p, _)| p.text.clone())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(PlaceholderError::Missing(missing));
+        }
+
+        // Any placeholders which were duplicated or invented by the translation engine will show up
+        // as extras when comparing the restored text against the source value.
+        let mut expected = shielded
+            .placeholders
+            .iter()
+            .filter(|p| p.literal)
+            .map(|p| p.text.as_str())
+            .collect::<Vec<_>>();
+        for part in &parts {
+            let TextPart::Literal(literal) = part else {
+                continue;
+            };
+
+            for found in self.find_all(literal) {
+                match expected.iter().position(|p| *p == found.as_str()) {
+                    Some(i) => {
+                        expected.swap_remove(i);
+                    }
+                    None => unexpected.push(found.as_str().to_owned()),
+                }
+            }
+        }
+
+        if !unexpected.is_empty() {
+            return Err(PlaceholderError::Unexpected(unexpected));
+        }
+
+        parts.retain(|p| !matches!(p, TextPart::Literal(l) if l.is_empty()));
+        Ok(parts)
+    }
+
+    /// Get the token which stands in for the placeholder at a given index.
+    fn token(index: usize) -> String {
+        format!("{{PH_{index}}}")
+    }
+
+    /// Find all placeholders in a value, in order of appearance.
+    fn find_all<'a>(&self, text: &'a str) -> Vec<regex::Match<'a>> {
+        self.pattern.find_iter(text).collect()
+    }
+}
+
+impl Display for PlaceholderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlaceholderError::Missing(placeholders) => write!(
+                f,
+                "translation is missing placeholder(s) {}",
+                placeholders.join(", ")
+            ),
+            PlaceholderError::Unexpected(placeholders) => write!(
+                f,
+                "translation contains unexpected placeholder(s) {}",
+                placeholders.join(", ")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(syntaxes: &[PlaceholderSyntax]) -> PlaceholderMatcher {
+        PlaceholderMatcher::new(&PlaceholderConfig {
+            syntaxes: syntaxes.to_vec(),
+            custom: Vec::new(),
+        })
+    }
+
+    /// Shield a value and restore it unchanged, returning the shielded text.
+    fn assert_round_trip(matcher: &PlaceholderMatcher, text: &str) -> String {
+        let shielded = matcher.shield(text);
+        assert_eq!(
+            matcher.restore(&shielded, &shielded.text).ok().as_deref(),
+            Some(text)
+        );
+        shielded.text
+    }
+
+    #[test]
+    fn shields_each_syntax() {
+        let cases = [
+            (
+                PlaceholderSyntax::Brace,
+                "Hello {name}, you have {0} new {item.type}",
+                "Hello {PH_0}, you have {PH_1} new {PH_2}",
+            ),
+            (
+                PlaceholderSyntax::DoubleBrace,
+                "{{count}} items for {{- name}}",
+                "{PH_0} items for {PH_1}",
+            ),
+            (
+                PlaceholderSyntax::Printf,
+                "%s has %1$d files, %.2f%% done, 50% off",
+                "{PH_0} has {PH_1} files, {PH_2}{PH_3} done, 50% off",
+            ),
+            (
+                PlaceholderSyntax::Colon,
+                "The :attr field is required at 10:30",
+                "The {PH_0} field is required at 10:30",
+            ),
+            (
+                PlaceholderSyntax::I18nextNesting,
+                "See $t(common.terms) for details",
+                "See {PH_0} for details",
+            ),
+        ];
+
+        for (syntax, text, expected) in cases {
+            assert_eq!(assert_round_trip(&matcher(&[syntax]), text), expected);
+        }
+    }
+
+    #[test]
+    fn prefers_double_braces() {
+        let matcher = matcher(&[PlaceholderSyntax::Brace, PlaceholderSyntax::DoubleBrace]);
+        assert_eq!(
+            assert_round_trip(&matcher, "{{count}} and {name}"),
+            "{PH_0} and {PH_1}"
+        );
+    }
+
+    #[test]
+    fn shields_custom_patterns() {
+        let matcher = PlaceholderMatcher::new(&PlaceholderConfig {
+            syntaxes: Vec::new(),
+            custom: vec![r"<%=\s*\w+\s*%>".to_owned()],
+        });
+        assert_eq!(
+            assert_round_trip(&matcher, "Hi <%= name %>, {name}"),
+            "Hi {PH_0}, {name}"
+        );
+    }
+
+    #[test]
+    fn restores_moved_and_respaced_tokens() {
+        let matcher = PlaceholderMatcher::new(&PlaceholderConfig::default());
+        let shielded = matcher.shield("{name} sent %d files");
+        assert_eq!(
+            matcher
+                .restore(&shielded, "{ PH_1 } Dateien von {PH_0 }")
+                .ok()
+                .as_deref(),
+            Some("%d Dateien von {name}")
+        );
+    }
+
+    #[test]
+    fn reports_missing_placeholders() {
+        let matcher = PlaceholderMatcher::new(&PlaceholderConfig::default());
+        let shielded = matcher.shield("{name} sent %d files");
+        let Err(PlaceholderError::Missing(missing)) =
+            matcher.restore(&shielded, "{PH_0} hat Dateien gesendet")
+        else {
+            panic!("expected a missing placeholder");
+        };
+        assert_eq!(missing, ["%d"]);
+    }
+
+    #[test]
+    fn reports_unexpected_placeholders() {
+        let matcher = PlaceholderMatcher::new(&PlaceholderConfig::default());
+        let shielded = matcher.shield("{name} sent files");
+
+        let Err(PlaceholderError::Unexpected(unexpected)) =
+            matcher.restore(&shielded, "{PH_0} hat {PH_1} Dateien gesendet")
+        else {
+            panic!("expected an unknown token to be unexpected");
+        };
+        assert_eq!(unexpected, ["{PH_1}"]);
+
+        let Err(PlaceholderError::Unexpected(unexpected)) =
+            matcher.restore(&shielded, "{PH_0} und {PH_0} haben %s Dateien gesendet")
+        else {
+            panic!("expected duplicated and invented placeholders to be unexpected");
+        };
+        assert_eq!(unexpected, ["{name}", "%s"]);
+    }
+
+    #[test]
+    fn shields_literal_text_which_looks_like_a_token() {
+        let matcher = matcher(&[PlaceholderSyntax::Printf]);
+        assert_eq!(
+            assert_round_trip(&matcher, "Type {PH_0} or { PH_1 } for %s"),
+            "Type {PH_0} or {PH_1} for {PH_2}"
+        );
+
+        let shielded = matcher.shield("Type {PH_0} for %s");
+        assert_eq!(
+            matcher
+                .restore(&shielded, "Für {PH_1} {PH_0} eingeben")
+                .ok()
+                .as_deref(),
+            Some("Für %s {PH_0} eingeben")
+        );
+    }
+
+    #[test]
+    fn keeps_placeholder_parts_separate() {
+        let matcher = matcher(&[PlaceholderSyntax::Brace]);
+        let shielded = matcher.shield_parts(&[
+            TextPart::Placeholder("#".to_owned()),
+            TextPart::Literal(" files for {name}".to_owned()),
+        ]);
+        assert_eq!(shielded.text, "{PH_0} files for {PH_1}");
+
+        let parts = matcher
+            .restore_parts(&shielded, "{PH_0} Dateien für {PH_1}")
+            .ok()
+            .unwrap();
+        let [TextPart::Placeholder(pound), TextPart::Literal(literal)] = parts.as_slice() else {
+            panic!("expected a placeholder followed by a literal");
+        };
+        assert_eq!(pound, "#");
+        assert_eq!(literal, " Dateien für {name}");
+    }
+}
@@ -0,0 +1,147 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::helper_functions::create_directory_if_not_exists;
+use crate::lockfile::Lockfile;
+use crate::types::LocaleDocument;
+use crate::{LOCKFILE_PATH, STAGING_DIR_PATH, exit};
+
+/// A group of locale files which are written to a staging directory first, then moved into place
+/// together once every file has been staged.
+///
+/// If moving any file into place fails, the files which were already moved are restored, so that
+/// either every file is updated or none are.
+pub struct Transaction {
+    staged_files: Vec<StagedFile>,
+}
+
+struct StagedFile {
+    staged_path: PathBuf,
+    target_path: PathBuf,
+}
+
+impl Transaction {
+    /// Start a new transaction, discarding anything left in the staging directory by a previous
+    /// run which was interrupted.
+    pub fn new() -> Self {
+        if Path::new(STAGING_DIR_PATH).exists()
+            && std::fs::remove_dir_all(STAGING_DIR_PATH).is_err()
+        {
+            exit!(
+                "Failed to clear staging directory <g>'{}'</>. Ensure that the file permissions are set correctly.",
+                STAGING_DIR_PATH
+            );
+        }
+
+        create_directory_if_not_exists(STAGING_DIR_PATH);
+        Transaction {
+            staged_files: Vec::new(),
+        }
+    }
+
+    /// Stage a [`LocaleDocument`] to be written to its own path.
+    pub fn stage(&mut self, document: LocaleDocument) {
+        let target_path = document.path().to_owned();
+        let staged_path = self.next_staged_path();
+
+        document.write_out(Some(staged_path.clone()));
+        self.staged_files.push(StagedFile {
+            staged_path,
+            target_path,
+        });
+    }
+
+    /// Stage a [`Lockfile`] to be written to its own path.
+    pub fn stage_lockfile(&mut self, lockfile: &Lockfile) {
+        let staged_path = self.next_staged_path();
+
+        lockfile.write_out(Some(staged_path.clone()));
+        self.staged_files.push(StagedFile {
+            staged_path,
+            target_path: PathBuf::from(LOCKFILE_PATH),
+        });
+    }
+
+    fn next_staged_path(&self) -> PathBuf {
+        Path::new(STAGING_DIR_PATH).join(format!("{}.json", self.staged_files.len()))
+    }
+
+    /// Move every staged file into place, exiting and restoring the original files if any of them
+    /// cannot be moved.
+    pub fn commit(self) {
+        // Each committed target, along with the backup of the file it replaced, if there was one.
+        let mut committed = Vec::new();
+        for (i, file) in self.staged_files.iter().enumerate() {
+            let backup_path = file
+                .target_path
+                .exists()
+                .then(|| Path::new(STAGING_DIR_PATH).join(format!("{i}.backup.json")));
+
+            if let Some(backup_path) = &backup_path
+                && move_file(&file.target_path, backup_path).is_err()
+            {
+                rollback(committed);
+                exit!(
+                    "Failed to replace <r>'{}'</>, so no files were changed. Ensure that the file permissions are set correctly.",
+                    file.target_path.to_string_lossy()
+                );
+            }
+
+            if place_file(&file.staged_path, &file.target_path).is_err() {
+                committed.push((file.target_path.clone(), backup_path));
+                rollback(committed);
+                exit!(
+                    "Failed to write <r>'{}'</>, so no files were changed. Ensure that the file permissions are set correctly.",
+                    file.target_path.to_string_lossy()
+                );
+            }
+
+            committed.push((file.target_path.clone(), backup_path));
+        }
+
+        let _ = std::fs::remove_dir_all(STAGING_DIR_PATH);
+    }
+}
+
+/// Restore the files replaced by a partially committed transaction, in reverse order.
+fn rollback(committed: Vec<(PathBuf, Option<PathBuf>)>) {
+    for (target_path, backup_path) in committed.into_iter().rev() {
+        let restored = match backup_path {
+            Some(backup_path) => move_file(&backup_path, &target_path),
+            None => match std::fs::remove_file(&target_path) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            },
+        };
+
+        if restored.is_err() {
+            exit!(
+                "Failed to restore <r>'{}'</> while rolling back. The original file can be found in <g>'{}'</>.",
+                target_path.to_string_lossy(),
+                STAGING_DIR_PATH
+            );
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(STAGING_DIR_PATH);
+}
+
+/// Move a staged file to its target path, creating any missing parent directories.
+fn place_file(staged_path: &Path, target_path: &Path) -> io::Result<()> {
+    if let Some(parent) = target_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    move_file(staged_path, target_path)
+}
+
+/// Move a file, falling back to copying it if it cannot be renamed, such as when moving between
+/// filesystems.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    std::fs::copy(from, to)?;
+    std::fs::remove_file(from)
+}
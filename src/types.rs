@@ -1,48 +1,86 @@
 use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use deepl_api::{DeepL, TranslatableTextList, TranslationOptions};
+use color_print::ceprintln;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map as JsonMap, Value as JsonValue};
 
+use crate::backend::{
+    DEFAULT_CONCURRENCY, Formality, RetryPolicy, TranslationBackend, TranslationError,
+    TranslationRequest,
+};
+use crate::glossary::{self, SyncedGlossary};
 use crate::helper_functions::{
-    create_directory_if_not_exists, create_parent_directories_if_not_exists,
+    create_directory_if_not_exists, create_parent_directories_if_not_exists, file_exists,
 };
-use crate::{APP_DIR_PATH, exit, interact};
-use crate::{MANIFEST_PATH, SOURCE_LOCALE_HISTORY_PATH};
+use crate::icu::IcuMessage;
+use crate::locale_file::{self, GettextConfig, LocaleFormat, LocaleLayout};
+use crate::lockfile::Lockfile;
+use crate::metadata::{self, FlatMetadata};
+use crate::placeholder::{PlaceholderConfig, PlaceholderError, PlaceholderMatcher, ShieldedText};
+use crate::verbatim::{VerbatimConfig, VerbatimRules};
+use crate::{APP_DIR_PATH, MANIFEST_PATH, exit, interact, key_path};
 
 pub type LocaleData = JsonMap<String, JsonValue>;
-
-pub struct DeepLContext {
-    api_connection: DeepL,
-    translation_options: TranslationOptions,
-    pub available_target_langs: Vec<Language>,
-}
+/// [`LocaleData`] flattened into a map from key paths (such as `auth.login.title`) to string
+/// values. See [`key_path::flatten()`].
+pub type FlatLocaleData = JsonMap<String, JsonValue>;
 
 pub struct AppData {
     manifest: LocaleManifest,
-    source_locale: LocaleDocument,
+    lockfile: Lockfile,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 struct LocaleManifestExternal {
+    #[serde(default = "default_source_language")]
+    source_language: String,
+    #[serde(default = "default_source_language_name")]
+    source_language_name: String,
     source_locale_path: PathBuf,
     locale_paths: BTreeMap<String, PathBuf>,
     language_names: BTreeMap<String, String>,
+    #[serde(default)]
+    placeholders: PlaceholderConfig,
+    #[serde(default)]
+    retry: RetryPolicy,
+    #[serde(default = "default_concurrency")]
+    concurrency: usize,
+    #[serde(default)]
+    glossaries: BTreeMap<String, SyncedGlossary>,
+    #[serde(default)]
+    formality: BTreeMap<String, Formality>,
+    #[serde(default)]
+    do_not_translate: VerbatimConfig,
+    #[serde(default)]
+    gettext: GettextConfig,
 }
 
 pub struct LocaleManifest {
+    pub source_language: Language,
     pub source_locale_path: PathBuf,
     pub locale_paths: BTreeMap<String, PathBuf>,
     pub languages: Vec<Language>,
+    pub placeholders: PlaceholderConfig,
+    pub retry: RetryPolicy,
+    pub concurrency: usize,
+    /// The synced glossary of each language pair, keyed by [`glossary::pair_name()`].
+    pub glossaries: BTreeMap<String, SyncedGlossary>,
+    /// The formality of each target language which does not use [`Formality::Default`].
+    pub formality: BTreeMap<String, Formality>,
+    /// The keys and values which are copied to every target locale instead of being translated.
+    pub do_not_translate: VerbatimConfig,
+    pub gettext: GettextConfig,
 }
 
 pub struct LocaleDocument {
     pub data: LocaleData,
     language: Language,
     path: PathBuf,
+    layout: LocaleLayout,
 }
 
 #[derive(Clone, PartialEq)]
@@ -51,9 +89,36 @@ pub struct Language {
     pub name: String,
 }
 
+/// The changes a single language needs to be brought up to date with the source locale. See
+/// [`Lockfile::diff()`].
 pub struct LocaleDataDiff {
-    changed_or_added: LocaleData,
-    removed: LocaleData,
+    pub changed_or_added: FlatLocaleData,
+    pub removed: Vec<String>,
+    /// Keys which were renamed or moved without their value changing, as pairs of old and new key
+    /// paths. Their translations are carried over instead of being translated again.
+    pub renamed: Vec<(String, String)>,
+    /// Keys which were edited by hand, and whose source value has changed since. They are kept as
+    /// they are, but need to be reviewed.
+    pub review: Vec<String>,
+}
+
+/// Settings which control how values are translated into a single language.
+pub struct TranslationSettings<'a> {
+    pub placeholders: PlaceholderMatcher,
+    pub glossary_id: Option<&'a str>,
+    pub formality: Formality,
+    /// Metadata about the source values, such as the context to translate them in.
+    pub metadata: FlatMetadata,
+    pub verbatim: VerbatimRules,
+}
+
+/// A source value which has been split up and shielded, ready to be sent to a translation backend.
+enum PreparedValue {
+    Plain(ShieldedText),
+    /// An ICU message whose literal fragments are translated individually.
+    Icu(IcuMessage, Vec<ShieldedText>),
+    /// A value which must not be translated, and is copied as it is.
+    Verbatim(String),
 }
 
 pub struct LanguageDiff {
@@ -62,66 +127,14 @@ pub struct LanguageDiff {
 }
 
 impl AppData {
-    pub fn new(manifest: LocaleManifest, source_locale: LocaleDocument) -> Self {
-        AppData {
-            manifest,
-            source_locale,
-        }
+    pub fn new(manifest: LocaleManifest, lockfile: Lockfile) -> Self {
+        AppData { manifest, lockfile }
     }
 
-    /// Write [`Self::manifest`] and [`Self::source_locale`] to their respective files.
+    /// Write [`Self::manifest`] and [`Self::lockfile`] to their respective files.
     pub fn write_out(self) {
         self.manifest.write_out();
-        self.source_locale
-            .write_out(Some(PathBuf::from(SOURCE_LOCALE_HISTORY_PATH)));
-    }
-}
-
-impl DeepLContext {
-    /// Connect to the DeepL API using a key specified by the `DEEPL_API_KEY` environment variable.
-    pub fn connect() -> Self {
-        let Ok(deepl_api_key) = std::env::var("DEEPL_API_KEY") else {
-            exit!(
-                "DeepL API key was not found. Set it using the DEEPL_API_KEY environment variable, which can be specified in the .env file if you prefer."
-            );
-        };
-
-        let api_connection = DeepL::new(deepl_api_key);
-        if !Self::valid_key(&api_connection) {
-            exit!("Provided DeepL API key is invalid.");
-        }
-
-        let translation_options = TranslationOptions {
-            split_sentences: None,
-            preserve_formatting: Some(true),
-            formality: None,
-            glossary_id: None,
-        };
-
-        let Ok(available_target_langs) = api_connection.target_languages() else {
-            exit!(
-                "Failed to fetch available target languages. This may be because of a connection issue with DeepL."
-            );
-        };
-
-        let available_target_langs = available_target_langs
-            .into_iter()
-            .map(|l| Language {
-                code: l.language,
-                name: l.name,
-            })
-            .collect();
-
-        DeepLContext {
-            api_connection,
-            translation_options,
-            available_target_langs,
-        }
-    }
-
-    /// Checks if the API key for a given DeepL connection is valid.
-    fn valid_key(api_connection: &DeepL) -> bool {
-        api_connection.usage_information().is_ok()
+        self.lockfile.write_out(None);
     }
 }
 
@@ -137,28 +150,74 @@ impl LocaleManifest {
     }
 
     /// Create a new project manifest by prompting the user.
-    pub fn from_user_setup() -> Self {
+    ///
+    /// The source language and source locale path are only prompted for if they are not provided,
+    /// and confirmation prompts are skipped if `assume_yes` is set.
+    pub fn from_user_setup(
+        backend: &dyn TranslationBackend,
+        source_language: Option<&str>,
+        source_locale_path: Option<PathBuf>,
+        assume_yes: bool,
+    ) -> Self {
         if LocaleManifest::get_existing().is_some() {
             exit!(
                 "Project has already been set up. To fully reset the project, remove the 'ltranslate' directory."
             );
         }
 
-        if !interact::confirm_prompt("Set up a new project in the current directory?") {
+        if !assume_yes
+            && !interact::confirm_prompt("Set up a new project in the current directory?")
+        {
             exit!("Setup canceled.");
         }
 
-        if !interact::confirm_prompt("Do you have an English locale file ready to be translated?") {
-            eprintln!("You will need an English locale file in order to set up ltranslate.");
-            exit!("Setup canceled.");
-        }
+        let source_language = match source_language {
+            Some(code) => interact::resolve_source_language(backend, code),
+            None if assume_yes => Language::english(),
+            None => interact::select_source_language(backend),
+        };
+
+        let source_locale_path = match source_locale_path {
+            Some(path) => {
+                if !file_exists(&path) {
+                    exit!(
+                        "Source locale file <r>'{}'</> does not exist.",
+                        path.to_string_lossy()
+                    );
+                }
 
-        let english_locale_path = interact::select_source_locale();
+                path
+            }
+            None => {
+                if !assume_yes
+                    && !interact::confirm_prompt(&format!(
+                        "Do you have a {} locale file ready to be translated?",
+                        source_language.name
+                    ))
+                {
+                    eprintln!(
+                        "You will need a {} locale file in order to set up ltranslate.",
+                        source_language.name
+                    );
+                    exit!("Setup canceled.");
+                }
+
+                interact::select_source_locale(&source_language)
+            }
+        };
 
         LocaleManifest {
-            source_locale_path: english_locale_path,
+            source_language,
+            source_locale_path,
             locale_paths: BTreeMap::new(),
             languages: Vec::new(),
+            placeholders: PlaceholderConfig::default(),
+            retry: RetryPolicy::default(),
+            concurrency: DEFAULT_CONCURRENCY,
+            glossaries: BTreeMap::new(),
+            formality: BTreeMap::new(),
+            do_not_translate: VerbatimConfig::default(),
+            gettext: GettextConfig::default(),
         }
     }
 
@@ -186,11 +245,54 @@ impl LocaleManifest {
         };
     }
 
+    /// Get the ID of the synced glossary which applies to translations into a given language by the
+    /// backend with a given identifier, if there is one.
+    pub fn glossary_id(&self, language: &Language, backend: &str) -> Option<&str> {
+        self.glossaries
+            .get(&glossary::pair_name(
+                &self.source_language.code,
+                &language.code,
+            ))
+            .filter(|g| g.backend == backend)
+            .map(|g| g.id.as_str())
+    }
+
+    /// Get the formality of a target language.
+    pub fn formality(&self, language: &Language) -> Formality {
+        self.formality
+            .get(&language.code)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Set the formality of a target language, warning if the backend cannot apply it.
+    pub fn set_formality(
+        &mut self,
+        backend: &dyn TranslationBackend,
+        language: &Language,
+        formality: Formality,
+    ) {
+        if formality == Formality::Default {
+            self.formality.remove(&language.code);
+            return;
+        }
+
+        if !formality.is_preference() && !backend.supports_formality(&language.code) {
+            ceprintln!(
+                "<y>Warning:</> <g>'{}'</> does not support formality, so this setting will be ignored.",
+                language.code
+            );
+        }
+
+        self.formality.insert(language.code.clone(), formality);
+    }
+
     /// Safely remove zero or more languages from [`LocaleManifest::locale_paths`] and
     /// [`LocaleManifest::languages`].
     pub fn remove_languages(&mut self, to_remove: &[Language]) {
         for removed_lang in to_remove {
             self.locale_paths.remove(&removed_lang.code);
+            self.formality.remove(&removed_lang.code);
             if let Some(lang_index) = self
                 .languages
                 .iter()
@@ -209,25 +311,14 @@ impl LocaleManifest {
 }
 
 impl LocaleDocument {
-    /// Get a [`LocaleDocument`] from the source locale history file, as specified by
-    /// [`SOURCE_LOCALE_HISTORY_PATH`].
-    pub fn source_history() -> Option<Self> {
-        let history_path = PathBuf::from(SOURCE_LOCALE_HISTORY_PATH);
-        Some(LocaleDocument {
-            data: Self::parse_data_from_file(&history_path)?,
-            language: Language::english(),
-            path: history_path,
-        })
-    }
-
     /// Get a [`LocaleDocument`] from the source locale file, as specified by
     /// [`LocaleManifest::source_locale_path`].
     pub fn source(manifest_data: &LocaleManifest) -> Option<Self> {
-        Some(LocaleDocument {
-            data: Self::parse_data_from_file(&manifest_data.source_locale_path)?,
-            language: Language::english(),
-            path: manifest_data.source_locale_path.clone(),
-        })
+        Self::read(
+            manifest_data.source_locale_path.clone(),
+            manifest_data.source_language.clone(),
+            true,
+        )
     }
 
     /// Get a [`LocaleDocument`] from an existing locale file, using
@@ -240,24 +331,20 @@ impl LocaleDocument {
             );
         };
 
-        Some(LocaleDocument {
-            data: Self::parse_data_from_file(&path)?,
-            language,
-            path,
-        })
+        Self::read(path, language, false)
     }
 
     /// Translate a [`LocaleDocument`] into a given language.
     ///
     /// Before calling this function, the language must be enabled, and the path must be present in
-    /// [`LocaleManifest::locale_paths`],
+    /// [`LocaleManifest::locale_paths`].
     pub fn translate_full(
-        deepl_context: &DeepLContext,
+        backend: &dyn TranslationBackend,
         manifest_data: &LocaleManifest,
         source_document: &LocaleDocument,
         source_text: &[String],
         language: Language,
-    ) -> Self {
+    ) -> Result<Self, TranslationError> {
         let Some(path) = manifest_data.locale_paths.get(&language.code).cloned() else {
             exit!(
                 "Could not find path for locale '{}' in the manifest.",
@@ -266,73 +353,130 @@ impl LocaleDocument {
         };
 
         let translated_data = LocaleDocument::translate_data(
-            deepl_context,
-            &source_document.data,
+            backend,
+            &TranslationSettings::new(manifest_data, backend, source_document, &language),
+            &source_document.flat_data(),
             source_text,
+            &source_document.language,
             &language,
-        );
-
-        LocaleDocument {
-            data: translated_data,
+        )?;
+        let translated_data =
+            locale_file::normalize(translated_data, LocaleFormat::of(&path), &language.code);
+
+        let mut layout = source_document.layout.for_language(&language.code);
+        layout.mark_translated(&translated_data, &manifest_data.gettext);
+        Ok(LocaleDocument {
+            data: key_path::rebuild(&source_document.data, &translated_data),
+            layout,
             language,
             path,
-        }
+        })
     }
 
     /// Translate a [`LocaleDocument`] into a given language.
     ///
-    /// Unlike [`LocaleDocument::translate_full()`], this function uses a given path instead of a
-    /// manifest file to set the [`LocaleDocument::path`].
+    /// Unlike [`LocaleDocument::translate_full()`], this function uses a given path and formality
+    /// instead of a manifest file to set the [`LocaleDocument::path`] and translation settings.
     pub fn translate_full_direct(
-        deepl_context: &DeepLContext,
-        source_data: &LocaleData,
+        backend: &dyn TranslationBackend,
+        source_document: &LocaleDocument,
         language: Language,
         path: PathBuf,
-    ) -> Self {
+        formality: Formality,
+    ) -> Result<Self, TranslationError> {
+        let source_data = &source_document.data;
+        let flat_source_data = source_document.flat_data();
+        let settings = TranslationSettings {
+            placeholders: PlaceholderMatcher::new(&PlaceholderConfig::default()),
+            glossary_id: None,
+            formality,
+            metadata: metadata::collect(source_data),
+            verbatim: VerbatimRules::new(&VerbatimConfig::default()),
+        };
         let translated_data = LocaleDocument::translate_data(
-            deepl_context,
-            &source_data,
-            &LocaleDocument::get_raw_text_data(source_data),
+            backend,
+            &settings,
+            &flat_source_data,
+            &LocaleDocument::get_raw_text_data(&flat_source_data),
+            &source_document.language,
             &language,
-        );
+        )?;
+        let translated_data =
+            locale_file::normalize(translated_data, LocaleFormat::of(&path), &language.code);
 
-        LocaleDocument {
-            data: translated_data,
+        Ok(LocaleDocument {
+            data: key_path::rebuild(source_data, &translated_data),
+            layout: source_document.layout.for_language(&language.code),
             language,
             path,
-        }
+        })
     }
 
     /// Retranslate a [`LocaleDocument`] into its given language, only translating values that have
     /// been created, updated, or deleted in the source locale file.
-    pub fn update_translations(&mut self, deepl_context: &DeepLContext, diff: &LocaleDataDiff) {
-        self.remove_dead_entries(&diff.removed);
+    ///
+    /// The updated document takes on the structure and key order of `source_document`, along with
+    /// the parts of its layout which come from the source locale (see
+    /// [`LocaleLayout::update_from_source()`]). Returns the translated entries, leaving out any
+    /// which were skipped.
+    pub fn update_translations(
+        &mut self,
+        backend: &dyn TranslationBackend,
+        manifest_data: &LocaleManifest,
+        source_document: &LocaleDocument,
+        diff: &LocaleDataDiff,
+    ) -> Result<FlatLocaleData, TranslationError> {
+        let mut flat_data = self.flat_data();
+        Self::rename_entries(&mut flat_data, &diff.renamed);
+        Self::remove_dead_entries(&mut flat_data, &diff.removed);
 
+        let mut translated_data = FlatLocaleData::new();
         if !diff.changed_or_added.is_empty() {
             let changed_or_added_text = LocaleDocument::get_raw_text_data(&diff.changed_or_added);
-            let translated_data = LocaleDocument::translate_data(
-                deepl_context,
+            translated_data = LocaleDocument::translate_data(
+                backend,
+                &TranslationSettings::new(manifest_data, backend, source_document, &self.language),
                 &diff.changed_or_added,
                 &changed_or_added_text,
+                &source_document.language,
                 &self.language,
+            )?;
+            translated_data = locale_file::normalize(
+                translated_data,
+                LocaleFormat::of(&self.path),
+                &self.language.code,
             );
 
-            self.update_entries(translated_data);
+            Self::update_entries(&mut flat_data, &translated_data);
         }
+
+        self.layout.update_from_source(&source_document.layout);
+        self.layout
+            .mark_translated(&translated_data, &manifest_data.gettext);
+        self.data = key_path::rebuild(&source_document.data, &flat_data);
+        Ok(translated_data)
     }
 
-    /// Translate a [`LocaleData`] map into a given language.
+    /// Translate a [`FlatLocaleData`] map into a given language.
     ///
-    /// This uses [`LocaleData`] in order to accommodate usage in both full and partial
+    /// This uses [`FlatLocaleData`] in order to accommodate usage in both full and partial
     /// translations, without being too internally complex. It is up to the caller to determine what
     /// values should be translated, and to merge translated data into a [`LocaleDocument`] as
     /// needed.
+    ///
+    /// Placeholders are shielded from the backend and restored afterwards. Any entry whose
+    /// translation does not contain exactly the same placeholders as its source value is reported
+    /// and left out of the returned data. A formality is only sent if the backend supports it for
+    /// the target language; otherwise it is ignored, with a warning unless it is only preferred.
+    /// Values with a context are sent along with it, in a separate request for each context.
     fn translate_data(
-        deepl_context: &DeepLContext,
-        source_data: &LocaleData,
+        backend: &dyn TranslationBackend,
+        settings: &TranslationSettings,
+        source_data: &FlatLocaleData,
         source_text: &[String],
+        source_language: &Language,
         language: &Language,
-    ) -> LocaleData {
+    ) -> Result<FlatLocaleData, TranslationError> {
         if source_data.len() != source_text.len() {
             exit!(
                 "The number of locale data entries does not match the number of raw text entries."
@@ -343,77 +487,172 @@ impl LocaleDocument {
             exit!("Provided locale data is empty and cannot be translated.");
         }
 
-        let text_to_translate = TranslatableTextList {
-            source_language: Some("EN".to_string()),
-            target_language: language.code.clone(),
-            texts: source_text.to_owned(),
-        };
+        let placeholders = &settings.placeholders;
+        let mut formality = settings.formality;
+        if formality != Formality::Default && !backend.supports_formality(&language.code) {
+            if !formality.is_preference() {
+                ceprintln!(
+                    "<y>Ignored formality</> <g>'{}'</> for locale <g>'{}'</>, which does not support it.",
+                    formality,
+                    language.code
+                );
+            }
 
-        let Ok(translated_data) = deepl_context.api_connection.translate(
-            Some(deepl_context.translation_options.clone()),
-            text_to_translate,
-        ) else {
-            exit!(
-                "Failed to translate values. This may be because of a connection issue with DeepL."
-            );
-        };
+            formality = Formality::Default;
+        }
 
-        if translated_data.len() != source_text.len() {
-            exit!("The number of translated values does not match the number of source values.");
+        // Values which must not be translated are never sent, so they are not billed either.
+        let prepared_values = source_data
+            .keys()
+            .zip(source_text)
+            .map(|(k, t)| match settings.is_verbatim(k, t) {
+                true => PreparedValue::Verbatim(t.clone()),
+                false => PreparedValue::new(t, placeholders, &language.code),
+            })
+            .collect::<Vec<_>>();
+        let texts = prepared_values
+            .iter()
+            .flat_map(|v| v.segments().iter().map(|t| t.text.clone()))
+            .collect::<Vec<_>>();
+
+        // A context applies to a whole request, so texts are sent in a separate request for each
+        // context. ICU messages without any literal text have nothing to translate.
+        let mut context_groups: Vec<(Option<&str>, Vec<usize>)> = Vec::new();
+        let text_contexts = source_data
+            .keys()
+            .zip(&prepared_values)
+            .flat_map(|(k, v)| std::iter::repeat_n(settings.context(k), v.segments().len()));
+        for (i, context) in text_contexts.enumerate() {
+            match context_groups.iter_mut().find(|(c, _)| *c == context) {
+                Some((_, indices)) => indices.push(i),
+                None => context_groups.push((context, vec![i])),
+            }
         }
 
-        source_data
+        let mut translated_data = vec![String::new(); texts.len()];
+        for (context, indices) in context_groups {
+            let group_texts = indices
+                .iter()
+                .map(|i| texts[*i].clone())
+                .collect::<Vec<_>>();
+            let translated_group = backend.translate(TranslationRequest {
+                source_language: &source_language.code,
+                target_language: &language.code,
+                texts: &group_texts,
+                glossary_id: settings.glossary_id,
+                formality,
+                context,
+                on_batch: None,
+            })?;
+
+            if translated_group.len() != group_texts.len() {
+                return Err(TranslationError::MismatchedResponse {
+                    expected: group_texts.len(),
+                    received: translated_group.len(),
+                });
+            }
+
+            for (i, translated) in indices.into_iter().zip(translated_group) {
+                translated_data[i] = translated;
+            }
+        }
+
+        let mut translated_segments = translated_data.into_iter();
+        Ok(source_data
             .keys()
-            .enumerate()
-            .map(|(i, k)| {
-                (
-                    k.clone(),
-                    JsonValue::String(translated_data[i].text.clone()),
-                )
+            .zip(prepared_values)
+            .filter_map(|(k, prepared)| {
+                let segment_count = prepared.segments().len();
+                let translated = translated_segments
+                    .by_ref()
+                    .take(segment_count)
+                    .collect::<Vec<_>>();
+
+                match prepared.finish(placeholders, translated) {
+                    Ok(restored) => Some((k.clone(), JsonValue::String(restored))),
+                    Err(e) => {
+                        ceprintln!(
+                            "<y>Skipped</> <g>'{}'</> for locale <g>'{}'</>: {}.",
+                            k,
+                            language.code,
+                            e
+                        );
+                        None
+                    }
+                }
             })
-            .collect()
+            .collect())
     }
 
-    /// Parse the [`LocaleJsonData`] from the file at the given path.
+    /// Read a [`LocaleDocument`] of a given language from the file at the given path, in the
+    /// format given by its extension (see [`LocaleFormat`]). `is_source` is set when reading a
+    /// source locale, which affects how untranslated PO entries are read.
     ///
     /// If the file is missing, returns [`None`]. This usually happens because a language has been
     /// added but a locale file has not yet been generated.
-    pub fn parse_data_from_file(path: &Path) -> Option<LocaleData> {
-        let locale_data = std::fs::read_to_string(path).ok()?;
-        let Ok(locale_data) = serde_json::from_str::<LocaleData>(&locale_data) else {
-            exit!("Failed to parse locale file.");
-        };
+    pub fn read(path: PathBuf, language: Language, is_source: bool) -> Option<Self> {
+        let text = std::fs::read_to_string(&path).ok()?;
+        let format = LocaleFormat::of(&path);
+        let (data, layout) = locale_file::parse(&text, format, &language.code, is_source)
+            .unwrap_or_else(|e| {
+                exit!(
+                    "Failed to parse locale file <g>'{}'</>: {}.",
+                    path.to_string_lossy(),
+                    e
+                )
+            });
+
+        Some(LocaleDocument {
+            data,
+            language,
+            path,
+            layout,
+        })
+    }
 
-        Some(locale_data)
+    /// Remove a given list of entries from the flattened [`LocaleDocument::data`].
+    ///
+    /// Entries which are already missing are ignored, as values that failed placeholder checks are
+    /// never written to the locale file.
+    fn remove_dead_entries(flat_data: &mut FlatLocaleData, to_remove: &[String]) {
+        to_remove.iter().for_each(|k| {
+            let _ = flat_data.shift_remove(k);
+        });
     }
 
-    /// Remove a given list of entries from the [`LocaleDocument::data`].
-    fn remove_dead_entries(&mut self, to_remove: &LocaleData) {
-        to_remove.keys().for_each(|k| {
-            let Some(_) = self.data.remove(k) else {
-                exit!(
-                    "Failed to remove key '{}' from locale '{}'.",
-                    k,
-                    self.language.code
-                );
-            };
+    /// Move entries of the flattened [`LocaleDocument::data`] from their old key paths to their new
+    /// ones.
+    fn rename_entries(flat_data: &mut FlatLocaleData, renamed: &[(String, String)]) {
+        renamed.iter().for_each(|(old_key, new_key)| {
+            if let Some(value) = flat_data.shift_remove(old_key) {
+                flat_data.insert(new_key.clone(), value);
+            }
         });
     }
 
-    /// Update a given list of entries in the [`LocaleDocument::data`].
-    fn update_entries(&mut self, to_update: LocaleData) {
-        to_update.into_iter().for_each(|(k, v)| {
-            self.data.insert(k, v);
+    /// Update a given list of entries in the flattened [`LocaleDocument::data`].
+    fn update_entries(flat_data: &mut FlatLocaleData, to_update: &FlatLocaleData) {
+        to_update.iter().for_each(|(k, v)| {
+            flat_data.insert(k.clone(), v.clone());
         });
     }
 
-    /// Get a [`Vec<String>`] representing all values from [`Self::data`].
+    /// Get the path the document is written to by default.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the [`LocaleDocument::data`] flattened into key paths.
+    pub fn flat_data(&self) -> FlatLocaleData {
+        key_path::flatten(&self.data)
+    }
+
+    /// Get a [`Vec<String>`] representing all values from a [`FlatLocaleData`] map.
     ///
     /// This is used to prevent repeated cloning when having to translate one document multiple
     /// times.
-    pub fn get_raw_text_data<'a>(data: impl Into<&'a LocaleData>) -> Vec<String> {
-        data.into()
-            .values()
+    pub fn get_raw_text_data(data: &FlatLocaleData) -> Vec<String> {
+        data.values()
             .clone()
             .map(|v| {
                 v.as_str()
@@ -426,66 +665,125 @@ impl LocaleDocument {
     /// Write the [`LocaleDocument`] to a file using its given path, or a different path if
     /// specified.
     pub fn write_out(self, override_path: Option<PathBuf>) {
-        let path = override_path.unwrap_or(self.path);
+        // The format always follows the document's own path, as staged files are written elsewhere.
+        let locale_data = locale_file::render(
+            &self.data,
+            LocaleFormat::of(&self.path),
+            &self.layout,
+            &self.language.code,
+        )
+        .unwrap_or_else(|e| {
+            exit!(
+                "Failed to format locale file <g>'{}'</>: {}.",
+                self.path.to_string_lossy(),
+                e
+            )
+        });
 
+        let path = override_path.unwrap_or_else(|| self.path.clone());
         create_parent_directories_if_not_exists(&path);
 
         let Ok(mut locale_file) = File::create(&path) else {
             exit!("Failed to create output file.");
         };
 
-        let Ok(locale_data) = serde_json::to_string_pretty(&self.data) else {
-            exit!("Failed to format output data.");
-        };
-
         let Ok(_) = locale_file.write_all(locale_data.as_bytes()) else {
             exit!("Failed to write data to output file.");
         };
     }
 }
 
-impl Language {
-    fn new(code: &str, name: &str) -> Self {
-        Language {
-            code: code.to_owned(),
-            name: name.to_owned(),
+impl<'a> TranslationSettings<'a> {
+    /// Get the settings for translating the source locale of a project into one of its target
+    /// languages using a given backend.
+    pub fn new(
+        manifest_data: &'a LocaleManifest,
+        backend: &dyn TranslationBackend,
+        source_document: &LocaleDocument,
+        language: &Language,
+    ) -> Self {
+        TranslationSettings {
+            placeholders: PlaceholderMatcher::new(&manifest_data.placeholders),
+            glossary_id: manifest_data.glossary_id(language, backend.identifier()),
+            formality: manifest_data.formality(language),
+            metadata: metadata::collect_project(&source_document.data),
+            verbatim: VerbatimRules::new(&manifest_data.do_not_translate),
         }
     }
 
-    fn english() -> Self {
-        Language {
-            code: "EN".to_owned(),
-            name: "English".to_owned(),
-        }
+    /// Get the context to translate the value at a key path in, if there is one.
+    fn context(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key)?.context.as_deref()
+    }
+
+    /// Check whether the value at a key path must be copied as it is instead of being translated,
+    /// either because it is marked with `"translate": false` or because it matches one of the
+    /// project's do-not-translate rules.
+    fn is_verbatim(&self, key: &str, text: &str) -> bool {
+        self.metadata.get(key).is_some_and(|m| m.verbatim) || self.verbatim.matches(key, text)
     }
 }
 
-impl LocaleDataDiff {
-    pub fn diff(original: &LocaleData, current: &LocaleData) -> Option<Self> {
-        if original == current {
-            return None;
+impl PreparedValue {
+    /// Prepare a source value for translation into a given language.
+    fn new(text: &str, placeholders: &PlaceholderMatcher, language_code: &str) -> Self {
+        match IcuMessage::parse(text) {
+            Some(mut message) => {
+                message.adapt_plurals(language_code);
+                let fragments = message
+                    .fragments()
+                    .iter()
+                    .map(|f| placeholders.shield_parts(f))
+                    .collect();
+                PreparedValue::Icu(message, fragments)
+            }
+            None => PreparedValue::Plain(placeholders.shield(text)),
         }
+    }
 
-        let changed_or_added = current
-            .iter()
-            .filter(|(k, v)| original.get(*k).map_or(true, |old_v| old_v != *v))
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect::<LocaleData>();
+    /// Get the shielded texts which need to be sent to the translation backend.
+    fn segments(&self) -> &[ShieldedText] {
+        match self {
+            PreparedValue::Plain(shielded) => std::slice::from_ref(shielded),
+            PreparedValue::Icu(_, fragments) => fragments,
+            PreparedValue::Verbatim(_) => &[],
+        }
+    }
 
-        let removed = original
-            .iter()
-            .filter(|(k, _)| !current.contains_key(*k))
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect::<LocaleData>();
+    /// Assemble the final translated value from the translations of [`PreparedValue::segments()`].
+    fn finish(
+        self,
+        placeholders: &PlaceholderMatcher,
+        translated: Vec<String>,
+    ) -> Result<String, PlaceholderError> {
+        match self {
+            PreparedValue::Plain(shielded) => placeholders.restore(&shielded, &translated[0]),
+            PreparedValue::Icu(message, fragments) => {
+                let translated_fragments = fragments
+                    .iter()
+                    .zip(translated)
+                    .map(|(shielded, t)| placeholders.restore_parts(shielded, &t))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(message.render(translated_fragments))
+            }
+            PreparedValue::Verbatim(text) => Ok(text),
+        }
+    }
+}
 
-        if changed_or_added.is_empty() && removed.is_empty() {
-            return None;
+impl Language {
+    fn new(code: &str, name: &str) -> Self {
+        Language {
+            code: code.to_owned(),
+            name: name.to_owned(),
         }
+    }
 
-        Some(LocaleDataDiff {
-            changed_or_added,
-            removed,
-        })
+    pub fn english() -> Self {
+        Language {
+            code: "EN".to_owned(),
+            name: "English".to_owned(),
+        }
     }
 }
 
@@ -511,27 +809,57 @@ impl LanguageDiff {
     }
 }
 
-impl ToString for &Language {
-    fn to_string(&self) -> String {
-        format!("{} | {}", self.code, self.name)
+impl Display for Language {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} | {}", self.code, self.name)
     }
 }
 
+/// Manifests created before the source language was configurable always used English.
+fn default_source_language() -> String {
+    Language::english().code
+}
+
+fn default_source_language_name() -> String {
+    Language::english().name
+}
+
+fn default_concurrency() -> usize {
+    DEFAULT_CONCURRENCY
+}
+
 impl From<LocaleManifestExternal> for LocaleManifest {
     fn from(value: LocaleManifestExternal) -> Self {
         let LocaleManifestExternal {
+            source_language,
+            source_language_name,
             source_locale_path,
             locale_paths,
             language_names,
+            placeholders,
+            retry,
+            concurrency,
+            glossaries,
+            formality,
+            do_not_translate,
+            gettext,
         } = value;
 
         LocaleManifest {
+            source_language: Language::new(&source_language, &source_language_name),
             source_locale_path,
             locale_paths,
             languages: language_names
                 .iter()
                 .map(|(c, n)| Language::new(c, n))
                 .collect(),
+            placeholders,
+            retry,
+            concurrency,
+            glossaries,
+            formality,
+            do_not_translate,
+            gettext,
         }
     }
 }
@@ -539,21 +867,32 @@ impl From<LocaleManifestExternal> for LocaleManifest {
 impl From<LocaleManifest> for LocaleManifestExternal {
     fn from(value: LocaleManifest) -> Self {
         let LocaleManifest {
+            source_language,
             source_locale_path,
             locale_paths,
             languages,
+            placeholders,
+            retry,
+            concurrency,
+            glossaries,
+            formality,
+            do_not_translate,
+            gettext,
         } = value;
 
         LocaleManifestExternal {
+            source_language: source_language.code,
+            source_language_name: source_language.name,
             source_locale_path,
             locale_paths,
             language_names: languages.into_iter().map(|l| (l.code, l.name)).collect(),
+            placeholders,
+            retry,
+            concurrency,
+            glossaries,
+            formality,
+            do_not_translate,
+            gettext,
         }
     }
 }
-
-impl<'a> From<&'a LocaleDocument> for &'a LocaleData {
-    fn from(value: &'a LocaleDocument) -> Self {
-        &value.data
-    }
-}
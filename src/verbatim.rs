@@ -0,0 +1,111 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::exit;
+
+/// The rules for values which must never be translated, such as URLs, brand names, or format
+/// strings, as stored in the manifest.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct VerbatimConfig {
+    /// Glob patterns matching key paths, where `*` matches within a single segment of a key path,
+    /// and `**` matches any number of segments.
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// Regular expressions which must match a value in its entirety, so that a value which merely
+    /// contains a URL, for example, is still translated.
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+/// Finds the values which are copied to every target locale as they are, instead of being
+/// translated.
+pub struct VerbatimRules {
+    key_pattern: Option<Regex>,
+    value_pattern: Option<Regex>,
+}
+
+impl VerbatimRules {
+    /// Compile the patterns for a given [`VerbatimConfig`], exiting if any value pattern is not a
+    /// valid regular expression.
+    pub fn new(config: &VerbatimConfig) -> Self {
+        for value_pattern in &config.values {
+            if Regex::new(value_pattern).is_err() {
+                exit!(
+                    "Do-not-translate pattern <r>'{}'</> is not a valid regular expression.",
+                    value_pattern
+                );
+            }
+        }
+
+        let key_patterns = config
+            .keys
+            .iter()
+            .map(|g| glob_pattern(g))
+            .collect::<Vec<_>>();
+
+        let value_patterns = config
+            .values
+            .iter()
+            .map(|p| format!("^(?:{p})$"))
+            .collect::<Vec<_>>();
+
+        VerbatimRules {
+            key_pattern: combine(&key_patterns),
+            value_pattern: combine(&value_patterns),
+        }
+    }
+
+    /// Check whether the value at a key path must be copied as it is.
+    pub fn matches(&self, key: &str, value: &str) -> bool {
+        self.key_pattern.as_ref().is_some_and(|p| p.is_match(key))
+            || self
+                .value_pattern
+                .as_ref()
+                .is_some_and(|p| p.is_match(value))
+    }
+}
+
+/// Combine several regular expressions into one which matches if any of them do.
+fn combine(patterns: &[String]) -> Option<Regex> {
+    let alternatives = patterns
+        .iter()
+        .map(|p| format!("(?:{p})"))
+        .collect::<Vec<_>>();
+
+    (!alternatives.is_empty()).then(|| {
+        Regex::new(&alternatives.join("|"))
+            .unwrap_or_else(|_| exit!("Failed to compile do-not-translate patterns."))
+    })
+}
+
+/// Convert a glob pattern for key paths into an anchored regular expression.
+///
+/// `*` matches any characters except an unescaped separator, `?` matches a single one of those,
+/// and `**` matches anything, including nothing at all when it is followed by a separator (so that
+/// `**.url` also matches `url`).
+fn glob_pattern(glob: &str) -> String {
+    const SEGMENT_CHARACTER: &str = r"(?:[^.\\]|\\.)";
+
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                match chars.peek() {
+                    Some('.') => {
+                        chars.next();
+                        pattern.push_str(r"(?:.*\.)?");
+                    }
+                    _ => pattern.push_str(".*"),
+                }
+            }
+            '*' => pattern.push_str(&format!("{SEGMENT_CHARACTER}*")),
+            '?' => pattern.push_str(SEGMENT_CHARACTER),
+            _ => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    pattern.push('$');
+    pattern
+}